@@ -0,0 +1,518 @@
+use crate::level::Level;
+use crate::settings::{Settings, VideoMode, WindowMode};
+use crate::simulation::{Court, Difficulty, MatchRules, MAX_PADDLES, TICKS_PER_SECOND};
+use crate::tournament::Format;
+
+const USAGE: &str = "Usage: pong-wgpu [options]
+
+Options:
+    --width <pixels>          initial window width
+    --height <pixels>         initial window height
+    --fullscreen              start in borderless fullscreen
+    --window-mode <mode>      windowed, borderless or exclusive
+    --monitor <index>         monitor to go fullscreen on
+    --video-mode <WxH[@Hz]>   resolution for exclusive fullscreen
+    --list-monitors           print the monitors and their video modes and exit
+    --mode <ai|local>         play against the computer or other local players
+    --controllers <list>      human or ai for each player, e.g. human,ai,ai,human
+    --players <names>         profile names for each player, e.g. Ann,Bob
+    --seed <number>           seed the match randomness
+    --win-score <points>      points needed to win a match
+    --court <court>           versus, four-player, doubles, breakout, survival
+                              or time-attack
+    --lives <count>           lives per player on the four-player and breakout courts
+    --time-limit <seconds>    how long a time attack lasts
+    --multiball <seconds>     serve an extra ball this often, 0 for never
+    --power-ups <seconds>     drop a power-up this often, 0 for never
+    --level <file>            play on a court layout from a level file
+    --difficulty <level>      easy, normal or hard computer opponent
+    --present-mode <mode>     fifo (vsync), mailbox or immediate
+    --frame-rate-limit <fps>  cap the frame rate, 0 for no cap
+    --backend <backend>       vulkan, metal, dx12, dx11, gl, primary or all
+    --power-preference <pref> low or high
+    --list-adapters           print the available graphics adapters and exit
+    --replay <file>           watch a recorded match
+    --tournament <names>      start a tournament, e.g. Ann,Bob,Cy,Dee
+    --tournament-format <fmt> single, double or round-robin
+    --resume-tournament       carry on with the last tournament
+    --export-stats <file>     write player profiles to a CSV file and exit
+    --headless                simulate without opening a window
+    --summary <file>          write a headless match's statistics as JSON
+    --max-ticks <ticks>       call a headless match a draw after this many ticks,
+                              0 for never (30 minutes by default)
+    --env                     run a learning environment over standard input
+                              and output, one JSON request per line
+    --pixels <WxH>            add greyscale pixel observations to the environment
+    --help                    print this message";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Controller {
+    Human,
+    Ai,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub window_mode: WindowMode,
+    pub monitor: Option<usize>,
+    pub video_mode: Option<VideoMode>,
+    pub list_monitors: bool,
+    pub controllers: [Controller; MAX_PADDLES],
+    pub players: Vec<Option<String>>,
+    pub seed: Option<u64>,
+    pub rules: MatchRules,
+    pub difficulty: Difficulty,
+    pub present_mode: wgpu::PresentMode,
+    pub frame_rate_limit: Option<u32>,
+    pub backend: wgpu::BackendBit,
+    pub power_preference: wgpu::PowerPreference,
+    pub list_adapters: bool,
+    pub replay: Option<String>,
+    pub tournament: Option<Vec<String>>,
+    pub tournament_format: Format,
+    pub resume_tournament: bool,
+    pub export_stats: Option<String>,
+    pub headless: bool,
+    pub summary: Option<String>,
+    pub max_ticks: u64,
+    pub env: bool,
+    pub pixels: Option<(u32, u32)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 800,
+            height: 600,
+            window_mode: WindowMode::Windowed,
+            monitor: None,
+            video_mode: None,
+            list_monitors: false,
+            controllers: [Controller::Human; MAX_PADDLES],
+            players: Vec::new(),
+            seed: None,
+            rules: MatchRules::default(),
+            difficulty: Difficulty::Normal,
+            present_mode: wgpu::PresentMode::Mailbox,
+            frame_rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            backend: wgpu::BackendBit::PRIMARY,
+            #[cfg(target_arch = "wasm32")]
+            backend: wgpu::BackendBit::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            list_adapters: false,
+            replay: None,
+            tournament: None,
+            tournament_format: Format::SingleElimination,
+            resume_tournament: false,
+            export_stats: None,
+            headless: false,
+            summary: None,
+            max_ticks: 30 * 60 * TICKS_PER_SECOND as u64,
+            env: false,
+            pixels: None,
+        }
+    }
+}
+
+impl Options {
+    // Settings provide the defaults, which command-line options then override.
+    pub fn from_settings(settings: &Settings) -> Self {
+        Options {
+            width: settings.video.width,
+            height: settings.video.height,
+            window_mode: settings.video.window_mode,
+            monitor: settings.video.monitor,
+            video_mode: settings.video.video_mode,
+            rules: settings.rules.clone(),
+            difficulty: settings.difficulty,
+            present_mode: settings.video.present_mode.into(),
+            frame_rate_limit: settings.video.frame_rate_limit,
+            power_preference: settings.video.power_preference.into(),
+            ..Options::default()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_args(options: Options) -> Self {
+        match Options::parse(std::env::args().skip(1), options) {
+            Ok(options) => options,
+            Err(error) => {
+                eprintln!("{}\n\n{}", error, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // Reads the same options from the page URL, e.g. `?mode=ai&win-score=3`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_query(options: Options) -> Self {
+        let query = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+        match Options::parse_query(&query, options.clone()) {
+            Ok(options) => options,
+            Err(error) => {
+                log::error!("{}", error);
+                options
+            }
+        }
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(
+        mut arguments: I,
+        mut options: Options,
+    ) -> Result<Self, String> {
+        while let Some(argument) = arguments.next() {
+            if argument == "--help" || argument == "-h" {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+
+            let argument = argument
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument '{}'", argument))?;
+            let (key, value) = match argument.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None if Options::is_flag(argument) => (argument.to_string(), None),
+                None => (argument.to_string(), arguments.next()),
+            };
+            options.set(&key, value.as_deref())?;
+        }
+        Ok(options)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn parse_query(query: &str, mut options: Options) -> Result<Self, String> {
+        for pair in query.trim_start_matches('?').split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+            match pair.split_once('=') {
+                Some((key, value)) => options.set(key, Some(value))?,
+                None => options.set(pair, None)?,
+            }
+        }
+        Ok(options)
+    }
+
+    fn is_flag(key: &str) -> bool {
+        matches!(
+            key,
+            "env"
+                | "fullscreen"
+                | "headless"
+                | "list-adapters"
+                | "list-monitors"
+                | "resume-tournament"
+        )
+    }
+
+    fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), String> {
+        if Options::is_flag(key) {
+            let enabled = match value {
+                None | Some("true") | Some("1") => true,
+                Some("false") | Some("0") => false,
+                Some(value) => return Err(format!("invalid value '{}' for '{}'", value, key)),
+            };
+            match key {
+                "fullscreen" if enabled => self.window_mode = WindowMode::Borderless,
+                "fullscreen" => self.window_mode = WindowMode::Windowed,
+                "list-adapters" => self.list_adapters = enabled,
+                "list-monitors" => self.list_monitors = enabled,
+                "resume-tournament" => self.resume_tournament = enabled,
+                "env" => self.env = enabled,
+                _ => self.headless = enabled,
+            }
+            return Ok(());
+        }
+
+        let value = value.ok_or_else(|| format!("missing value for '{}'", key))?;
+        let invalid = || format!("invalid value '{}' for '{}'", value, key);
+        match key {
+            "width" => self.width = value.parse().map_err(|_| invalid())?,
+            "height" => self.height = value.parse().map_err(|_| invalid())?,
+            "window-mode" => {
+                self.window_mode = match value {
+                    "windowed" => WindowMode::Windowed,
+                    "borderless" => WindowMode::Borderless,
+                    "exclusive" => WindowMode::Exclusive,
+                    _ => return Err(invalid()),
+                }
+            }
+            "monitor" => self.monitor = Some(value.parse().map_err(|_| invalid())?),
+            "video-mode" => self.video_mode = Some(parse_video_mode(value).ok_or_else(invalid)?),
+            "seed" => self.seed = Some(value.parse().map_err(|_| invalid())?),
+            "win-score" => {
+                self.rules.win_score = match value.parse() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(win_score) => win_score,
+                }
+            }
+            "difficulty" => {
+                self.difficulty = match value {
+                    "easy" => Difficulty::Easy,
+                    "normal" => Difficulty::Normal,
+                    "hard" => Difficulty::Hard,
+                    _ => return Err(invalid()),
+                }
+            }
+            // The first player is always human; the others are all played by the
+            // computer or all by people.
+            "mode" => {
+                self.controllers = match value {
+                    "ai" => {
+                        let mut controllers = [Controller::Ai; MAX_PADDLES];
+                        controllers[0] = Controller::Human;
+                        controllers
+                    }
+                    "local" => [Controller::Human; MAX_PADDLES],
+                    "host" | "join" => {
+                        return Err(format!(
+                            "'{}' mode requires networked play, which is not supported yet",
+                            value
+                        ))
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            "controllers" => {
+                let values: Vec<&str> = value.split(',').collect();
+                if values.len() > MAX_PADDLES {
+                    return Err(invalid());
+                }
+                for (controller, value) in self.controllers.iter_mut().zip(values) {
+                    *controller = match value {
+                        "human" => Controller::Human,
+                        "ai" => Controller::Ai,
+                        _ => return Err(invalid()),
+                    }
+                }
+            }
+            // Players left blank, e.g. `Ann,,Cy`, play without a profile.
+            "players" => {
+                let players: Vec<Option<String>> = value
+                    .split(',')
+                    .map(|name| Some(name.trim().to_string()).filter(|name| !name.is_empty()))
+                    .collect();
+                if players.len() > MAX_PADDLES {
+                    return Err(invalid());
+                }
+                self.players = players;
+            }
+            "court" => {
+                self.rules.court = match value {
+                    "versus" => Court::Versus,
+                    "four-player" => Court::FourPlayer,
+                    "doubles" => Court::Doubles,
+                    "breakout" => Court::Breakout,
+                    "survival" => Court::Survival,
+                    "time-attack" => Court::TimeAttack,
+                    _ => return Err(invalid()),
+                }
+            }
+            "lives" => {
+                self.rules.lives = match value.parse() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(lives) => lives,
+                }
+            }
+            "time-limit" => {
+                self.rules.time_limit = match value.parse::<f32>() {
+                    Ok(seconds) if seconds > 0.0 => seconds,
+                    _ => return Err(invalid()),
+                }
+            }
+            "multiball" => {
+                self.rules.multiball.spawn_interval = match value.parse::<f32>() {
+                    Ok(seconds) if seconds >= 0.0 => seconds,
+                    _ => return Err(invalid()),
+                }
+            }
+            "power-ups" => {
+                self.rules.power_ups.spawn_interval = match value.parse::<f32>() {
+                    Ok(seconds) if seconds >= 0.0 => seconds,
+                    _ => return Err(invalid()),
+                }
+            }
+            "level" => {
+                self.rules.level = Level::load(std::path::Path::new(value))
+                    .map_err(|error| format!("{}: {}", value, error))?
+            }
+            "present-mode" => {
+                self.present_mode = match value {
+                    "fifo" => wgpu::PresentMode::Fifo,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    "immediate" => wgpu::PresentMode::Immediate,
+                    _ => return Err(invalid()),
+                }
+            }
+            "frame-rate-limit" => {
+                self.frame_rate_limit = match value.parse() {
+                    Ok(0) => None,
+                    Ok(frame_rate_limit) => Some(frame_rate_limit),
+                    Err(_) => return Err(invalid()),
+                }
+            }
+            "backend" => {
+                self.backend = match value {
+                    "vulkan" => wgpu::BackendBit::VULKAN,
+                    "metal" => wgpu::BackendBit::METAL,
+                    "dx12" => wgpu::BackendBit::DX12,
+                    "dx11" => wgpu::BackendBit::DX11,
+                    "gl" => wgpu::BackendBit::GL,
+                    "primary" => wgpu::BackendBit::PRIMARY,
+                    "all" => wgpu::BackendBit::all(),
+                    _ => return Err(invalid()),
+                }
+            }
+            "power-preference" => {
+                self.power_preference = match value {
+                    "low" => wgpu::PowerPreference::LowPower,
+                    "high" => wgpu::PowerPreference::HighPerformance,
+                    _ => return Err(invalid()),
+                }
+            }
+            "replay" => self.replay = Some(value.to_string()),
+            "tournament" => {
+                let players: Vec<String> = value
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                if players.len() < 2 {
+                    return Err(invalid());
+                }
+                self.tournament = Some(players);
+            }
+            "tournament-format" => {
+                self.tournament_format = match value {
+                    "single" => Format::SingleElimination,
+                    "double" => Format::DoubleElimination,
+                    "round-robin" => Format::RoundRobin,
+                    _ => return Err(invalid()),
+                }
+            }
+            "export-stats" => self.export_stats = Some(value.to_string()),
+            "summary" => self.summary = Some(value.to_string()),
+            "max-ticks" => self.max_ticks = value.parse().map_err(|_| invalid())?,
+            "pixels" => {
+                let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                self.pixels = match (width.parse(), height.parse()) {
+                    (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(format!("unknown option '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+// Parses `1920x1080` or `1920x1080@144`.
+fn parse_video_mode(value: &str) -> Option<VideoMode> {
+    let (size, refresh_rate) = match value.split_once('@') {
+        Some((size, refresh_rate)) => (size, Some(refresh_rate.parse().ok()?)),
+        None => (value, None),
+    };
+    let (width, height) = size.split_once('x')?;
+    Some(VideoMode {
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+        refresh_rate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &str) -> Result<Options, String> {
+        Options::parse(
+            arguments.split_whitespace().map(str::to_string),
+            Options::default(),
+        )
+    }
+
+    #[test]
+    fn values_follow_the_option_or_an_equals_sign() {
+        let options = parse("--court four-player --lives=2 --seed 7 --headless").unwrap();
+        assert_eq!(options.rules.court, Court::FourPlayer);
+        assert_eq!(options.rules.lives, 2);
+        assert_eq!(options.seed, Some(7));
+        assert!(options.headless);
+        // Flags can be turned off again, and a later option wins.
+        let options = parse("--headless --headless=false --win-score 3 --win-score 9").unwrap();
+        assert!(!options.headless);
+        assert_eq!(options.rules.win_score, 9);
+    }
+
+    #[test]
+    fn options_start_from_the_given_defaults() {
+        let mut settings = Settings::default();
+        settings.rules.win_score = 11;
+        settings.video.frame_rate_limit = Some(60);
+        let options = Options::parse(
+            ["--frame-rate-limit", "0"]
+                .iter()
+                .map(|argument| argument.to_string()),
+            Options::from_settings(&settings),
+        )
+        .unwrap();
+        assert_eq!(options.rules.win_score, 11);
+        assert_eq!(options.frame_rate_limit, None);
+    }
+
+    #[test]
+    fn lists_and_sizes_are_parsed() {
+        let options = parse(
+            "--players Ann,,Cy --controllers human,ai --video-mode 1920x1080@144 --pixels 84x84",
+        )
+        .unwrap();
+        assert_eq!(
+            options.players,
+            vec![Some("Ann".to_string()), None, Some("Cy".to_string())]
+        );
+        assert_eq!(
+            options.controllers[..2],
+            [Controller::Human, Controller::Ai]
+        );
+        assert_eq!(
+            options.video_mode,
+            Some(VideoMode {
+                width: 1920,
+                height: 1080,
+                refresh_rate: Some(144),
+            })
+        );
+        assert_eq!(options.pixels, Some((84, 84)));
+        let options = parse("--mode ai --tournament Ann,Bob --tournament-format double").unwrap();
+        assert_eq!(options.controllers[0], Controller::Human);
+        assert_eq!(options.controllers[1..], [Controller::Ai; MAX_PADDLES - 1]);
+        assert_eq!(options.tournament_format, Format::DoubleElimination);
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        for arguments in [
+            "--win-score 0",
+            "--lives 0",
+            "--time-limit -5",
+            "--multiball -1",
+            "--court squash",
+            "--players a,b,c,d,e",
+            "--tournament Ann",
+            "--pixels 0x84",
+            "--video-mode 1920",
+            "--headless=maybe",
+            "--mode host",
+            "--width",
+            "--unknown 1",
+            "stray",
+        ] {
+            assert!(parse(arguments).is_err(), "{} was accepted", arguments);
+        }
+    }
+}
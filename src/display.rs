@@ -0,0 +1,131 @@
+use crate::settings::{VideoMode, WindowMode};
+
+pub fn fullscreen(
+    window: &winit::window::Window,
+    window_mode: WindowMode,
+    monitor: Option<usize>,
+    video_mode: Option<VideoMode>,
+) -> Option<winit::window::Fullscreen> {
+    let monitor = select_monitor(window, monitor);
+    match window_mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(winit::window::Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => {
+            match monitor
+                .as_ref()
+                .and_then(|monitor| select_video_mode(monitor, video_mode))
+            {
+                Some(video_mode) => Some(winit::window::Fullscreen::Exclusive(video_mode)),
+                // The browser and some window systems don't expose video modes.
+                None => {
+                    log::warn!("no matching video mode, using borderless fullscreen instead");
+                    Some(winit::window::Fullscreen::Borderless(monitor))
+                }
+            }
+        }
+    }
+}
+
+fn select_monitor(
+    window: &winit::window::Window,
+    index: Option<usize>,
+) -> Option<winit::monitor::MonitorHandle> {
+    if let Some(index) = index {
+        match window.available_monitors().nth(index) {
+            Some(monitor) => return Some(monitor),
+            None => log::warn!("monitor {} not found, using the current monitor", index),
+        }
+    }
+    window.current_monitor()
+}
+
+// Without a requested resolution the monitor's native one is preferred. Ties go to
+// the highest refresh rate and then the deepest colour.
+fn select_video_mode(
+    monitor: &winit::monitor::MonitorHandle,
+    requested: Option<VideoMode>,
+) -> Option<winit::monitor::VideoMode> {
+    let native_size = monitor.size();
+    let matches = |video_mode: &winit::monitor::VideoMode| {
+        let size = video_mode.size();
+        match requested {
+            Some(requested) => {
+                (size.width, size.height) == (requested.width, requested.height)
+                    && (requested.refresh_rate.is_none()
+                        || requested.refresh_rate == Some(video_mode.refresh_rate()))
+            }
+            None => size == native_size,
+        }
+    };
+    let rank = |video_mode: &winit::monitor::VideoMode| {
+        let size = video_mode.size();
+        (
+            size.width * size.height,
+            video_mode.refresh_rate(),
+            video_mode.bit_depth(),
+        )
+    };
+
+    let best_match = monitor.video_modes().filter(matches).max_by_key(rank);
+    match (best_match, requested) {
+        (Some(video_mode), _) => Some(video_mode),
+        (None, Some(requested)) => {
+            log::warn!(
+                "video mode {}x{} is not available on this monitor",
+                requested.width,
+                requested.height
+            );
+            None
+        }
+        (None, None) => monitor.video_modes().max_by_key(rank),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_monitors(event_loop: &winit::event_loop::EventLoop<()>) {
+    for (index, monitor) in event_loop.available_monitors().enumerate() {
+        let size = monitor.size();
+        println!(
+            "{}: {} ({}x{})",
+            index,
+            monitor
+                .name()
+                .unwrap_or_else(|| "unknown monitor".to_string()),
+            size.width,
+            size.height
+        );
+        let mut video_modes: Vec<_> = monitor.video_modes().collect();
+        video_modes.sort_by_key(|video_mode| {
+            let size = video_mode.size();
+            std::cmp::Reverse((size.width, size.height, video_mode.refresh_rate()))
+        });
+        video_modes.dedup_by_key(|video_mode| (video_mode.size(), video_mode.refresh_rate()));
+        for video_mode in video_modes {
+            let size = video_mode.size();
+            println!(
+                "    {}x{}@{}",
+                size.width,
+                size.height,
+                video_mode.refresh_rate()
+            );
+        }
+    }
+}
+
+// Entering fullscreen in the browser doesn't resize the canvas, so it is matched to
+// the page while fullscreen and restored to the configured size afterwards.
+#[cfg(target_arch = "wasm32")]
+pub fn fit_canvas(window: &winit::window::Window, fullscreen: bool, width: u32, height: u32) {
+    let page_size = web_sys::window().and_then(|page| {
+        Some((
+            page.inner_width().ok()?.as_f64()?,
+            page.inner_height().ok()?.as_f64()?,
+        ))
+    });
+    match page_size {
+        Some((page_width, page_height)) if fullscreen => {
+            window.set_inner_size(winit::dpi::LogicalSize::new(page_width, page_height))
+        }
+        _ => window.set_inner_size(winit::dpi::LogicalSize::new(width, height)),
+    }
+}
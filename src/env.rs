@@ -0,0 +1,257 @@
+use crate::scene::{self, Shade};
+use crate::simulation::{
+    Court, Difficulty, InputFrame, MatchRules, PaddleInput, Simulation, TICKS_PER_SECOND,
+};
+use serde::Serialize;
+
+// Shades of the pixel observations, on a black court.
+const FOREGROUND_SHADE: u8 = 255;
+const HIGHLIGHT_SHADE: u8 = 128;
+
+// The vector observation is the first ball's position and velocity followed by
+// every paddle's position, in court units and court units per second. Pixels
+// are the court drawn in greyscale, a row at a time from the top.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Observation {
+    pub vector: Vec<f32>,
+    pub pixels: Option<Vec<u8>>,
+}
+
+// A match for a learning agent, which plays the first paddle while the computer
+// plays the others. Each step is one simulation tick, with the agent's action a
+// paddle movement from -1 to 1. The reward is 1 for every goal the agent's team
+// scores or life another team loses and -1 the other way round, plus any
+// challenge points scored.
+pub struct PongEnv {
+    rules: MatchRules,
+    difficulty: Difficulty,
+    pixel_size: Option<(u32, u32)>,
+    simulation: Simulation,
+    done: bool,
+}
+
+impl PongEnv {
+    pub fn new(rules: MatchRules, difficulty: Difficulty, pixel_size: Option<(u32, u32)>) -> Self {
+        PongEnv {
+            simulation: Simulation::new(0, rules.clone()),
+            rules,
+            difficulty,
+            pixel_size,
+            done: false,
+        }
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.simulation = Simulation::new(seed, self.rules.clone());
+        self.done = false;
+        self.observation()
+    }
+
+    // Stepping a finished match does nothing until it is reset.
+    pub fn step(&mut self, action: f32) -> (Observation, f32, bool) {
+        if self.done {
+            return (self.observation(), 0.0, true);
+        }
+        let mut frame = InputFrame::default();
+        frame.paddles[0] = PaddleInput::from_axis(action);
+        for player in 1..self.simulation.paddles.len() {
+            frame.paddles[player] = self.simulation.ai_input(player, self.difficulty);
+        }
+
+        let score = self.simulation.score.clone();
+        let points = self.simulation.points;
+        self.done = self.simulation.step(frame).is_some();
+        let reward = self.reward(&score, points);
+        (self.observation(), reward, self.done)
+    }
+
+    fn reward(&self, previous_score: &[u32], previous_points: u32) -> f32 {
+        let simulation = &self.simulation;
+        let agent_team = simulation.paddles[0].team;
+        let mut reward = 0.0;
+        for (team, (&score, &previous_score)) in
+            simulation.score.iter().zip(previous_score).enumerate()
+        {
+            // A goal adds to the scorers' score and a lost life takes one away
+            // from the team that lost it, so either way a rise is good for the team.
+            let change = score as f32 - previous_score as f32;
+            if team == agent_team {
+                reward += change;
+            } else {
+                reward -= change;
+            }
+        }
+        if matches!(simulation.rules.court, Court::Breakout | Court::Survival) {
+            reward += (simulation.points - previous_points) as f32;
+        }
+        reward
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn observation(&self) -> Observation {
+        let simulation = &self.simulation;
+        let mut vector = match simulation.balls.first() {
+            Some(ball) => {
+                let velocity = ball.heading() * 0.01 * ball.speed * TICKS_PER_SECOND as f32;
+                vec![ball.position.x, ball.position.y, velocity.x, velocity.y]
+            }
+            None => vec![0.0; 4],
+        };
+        for paddle in &simulation.paddles {
+            vector.extend([paddle.position.x, paddle.position.y]);
+        }
+        Observation {
+            vector,
+            pixels: self
+                .pixel_size
+                .map(|(width, height)| self.pixels(width, height)),
+        }
+    }
+
+    // Draws the same shapes as the window, in greyscale.
+    fn pixels(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0; (width * height) as usize];
+        let to_column = |x: f32| {
+            ((x + 1.0) / 2.0 * width as f32)
+                .round()
+                .clamp(0.0, width as f32)
+        };
+        let to_row = |y: f32| {
+            ((1.0 - y) / 2.0 * height as f32)
+                .round()
+                .clamp(0.0, height as f32)
+        };
+        for shape in scene::court_shapes(&self.simulation) {
+            let (position, half_size) = (shape.position, shape.half_size);
+            let left = to_column(position.x - half_size.x);
+            let top = to_row(position.y + half_size.y);
+            // Anything on the court shows up as at least one pixel.
+            let right = to_column(position.x + half_size.x)
+                .max(left + 1.0)
+                .min(width as f32);
+            let bottom = to_row(position.y - half_size.y)
+                .max(top + 1.0)
+                .min(height as f32);
+            let shade = match shape.shade {
+                Shade::Foreground => FOREGROUND_SHADE,
+                Shade::Highlight => HIGHLIGHT_SHADE,
+            };
+            for row in top as u32..bottom as u32 {
+                for column in left as u32..right as u32 {
+                    pixels[(row * width + column) as usize] = shade;
+                }
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_STEPS: usize = 100_000;
+
+    // Sweeps the paddle up and down, so the agent hits some balls and misses
+    // others.
+    fn action(step: usize) -> f32 {
+        ((step / 40) % 3) as f32 - 1.0
+    }
+
+    fn play(env: &mut PongEnv, seed: u64, steps: usize) -> Vec<(Observation, f32, bool)> {
+        let mut transitions = vec![(env.reset(seed), 0.0, false)];
+        for step in 0..steps {
+            transitions.push(env.step(action(step)));
+        }
+        transitions
+    }
+
+    // Plays to the end and returns the total reward.
+    fn play_out(env: &mut PongEnv, seed: u64) -> f32 {
+        env.reset(seed);
+        let mut total = 0.0;
+        for step in 0..MAX_STEPS {
+            let (_, reward, done) = env.step(action(step));
+            total += reward;
+            if done {
+                return total;
+            }
+        }
+        panic!("match did not end in {} steps", MAX_STEPS);
+    }
+
+    #[test]
+    fn same_seed_and_actions_give_the_same_episode() {
+        let rules = MatchRules {
+            court: Court::FourPlayer,
+            ..MatchRules::default()
+        };
+        let mut env = PongEnv::new(rules, Difficulty::Normal, Some((32, 24)));
+        let first = play(&mut env, 7, 3000);
+        let second = play(&mut env, 7, 3000);
+        assert!(first == second);
+        let other_seed = play(&mut env, 8, 3000);
+        assert!(first != other_seed);
+    }
+
+    #[test]
+    fn versus_rewards_add_up_to_the_goal_difference() {
+        let rules = MatchRules {
+            win_score: 2,
+            ..MatchRules::default()
+        };
+        let mut env = PongEnv::new(rules, Difficulty::Easy, None);
+        let total = play_out(&mut env, 3);
+        let score = &env.simulation().score;
+        assert_eq!(total, score[0] as f32 - score[1] as f32);
+        assert_ne!(total, 0.0);
+    }
+
+    #[test]
+    fn survival_rewards_returns_and_penalises_the_miss() {
+        let rules = MatchRules {
+            court: Court::Survival,
+            ..MatchRules::default()
+        };
+        let mut env = PongEnv::new(rules, Difficulty::Normal, None);
+        let total = play_out(&mut env, 5);
+        assert_eq!(total, env.simulation().points as f32 - 1.0);
+    }
+
+    #[test]
+    fn stepping_a_finished_match_does_nothing() {
+        let rules = MatchRules {
+            court: Court::Survival,
+            ..MatchRules::default()
+        };
+        let mut env = PongEnv::new(rules, Difficulty::Normal, None);
+        play_out(&mut env, 5);
+        let tick = env.simulation().tick;
+        let (_, reward, done) = env.step(1.0);
+        assert_eq!((reward, done), (0.0, true));
+        assert_eq!(env.simulation().tick, tick);
+    }
+
+    #[test]
+    fn observations_describe_the_ball_and_paddles() {
+        let mut env = PongEnv::new(MatchRules::default(), Difficulty::Normal, Some((40, 30)));
+        let observation = env.reset(1);
+        let simulation = env.simulation();
+        let ball = simulation.balls[0];
+        assert_eq!(observation.vector.len(), 4 + 2 * simulation.paddles.len());
+        assert_eq!(
+            &observation.vector[..2],
+            &[ball.position.x, ball.position.y]
+        );
+        let pixels = observation.pixels.expect("pixels were asked for");
+        assert_eq!(pixels.len(), 40 * 30);
+        // The ball starts in the middle and the paddles at either side.
+        assert_eq!(pixels[15 * 40 + 20], FOREGROUND_SHADE);
+        assert_eq!(pixels[15 * 40 + 2], FOREGROUND_SHADE);
+        assert_eq!(pixels[15 * 40 + 38], FOREGROUND_SHADE);
+        assert_eq!(pixels[2 * 40 + 20], 0);
+    }
+}
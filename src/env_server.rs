@@ -0,0 +1,61 @@
+use crate::cli::Options;
+use pong_wgpu::env::{Observation, PongEnv};
+use serde::{Deserialize, Serialize};
+
+// One JSON request per line, `{"reset": <seed>}` or `{"step": <action>}`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Reset(u64),
+    Step(f32),
+}
+
+#[derive(Serialize)]
+struct Response<'a> {
+    observation: &'a Observation,
+    reward: f32,
+    done: bool,
+}
+
+// Runs an environment for another process, e.g. a training script, answering
+// each request on standard input with a line of JSON on standard output. An
+// invalid request ends the run.
+pub fn run(options: &Options) {
+    use std::io::{BufRead, Write};
+
+    let mut env = PongEnv::new(options.rules.clone(), options.difficulty, options.pixels);
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                log::error!("could not read request: {}", error);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (observation, reward, done) = match serde_json::from_str(&line) {
+            Ok(Request::Reset(seed)) => (env.reset(seed), 0.0, false),
+            Ok(Request::Step(action)) => env.step(action),
+            Err(error) => {
+                log::error!("invalid request '{}': {}", line, error);
+                return;
+            }
+        };
+        let response = Response {
+            observation: &observation,
+            reward,
+            done,
+        };
+        let written = serde_json::to_writer(&mut stdout, &response)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(stdout))
+            .and_then(|_| stdout.flush());
+        if written.is_err() {
+            return;
+        }
+    }
+}
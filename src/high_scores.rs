@@ -0,0 +1,133 @@
+use crate::simulation::Court;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+
+const STORAGE_NAME: &str = "high_scores";
+const TABLE_SIZE: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub points: u32,
+}
+
+// The best scores for each challenge, highest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    pub breakout: Vec<HighScore>,
+    pub survival: Vec<HighScore>,
+    pub time_attack: Vec<HighScore>,
+}
+
+impl HighScores {
+    // A missing or unreadable table starts out empty.
+    pub fn load() -> Self {
+        match storage::load(STORAGE_NAME) {
+            Ok(high_scores) => high_scores.unwrap_or_default(),
+            Err(error) => {
+                log::error!("could not load high scores: {}", error);
+                HighScores::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(STORAGE_NAME, self) {
+            log::error!("could not save high scores: {}", error);
+        }
+    }
+
+    pub fn table(&self, court: Court) -> &[HighScore] {
+        match court {
+            Court::Breakout => &self.breakout,
+            Court::Survival => &self.survival,
+            Court::TimeAttack => &self.time_attack,
+            _ => &[],
+        }
+    }
+
+    // Returns the place the score took in the table, if it made it in. Later
+    // scores go below earlier ones they tie with.
+    pub fn add(&mut self, court: Court, high_score: HighScore) -> Option<usize> {
+        let table = match court {
+            Court::Breakout => &mut self.breakout,
+            Court::Survival => &mut self.survival,
+            Court::TimeAttack => &mut self.time_attack,
+            _ => return None,
+        };
+        let place = table
+            .iter()
+            .position(|entry| entry.points < high_score.points)
+            .unwrap_or(table.len());
+        if place >= TABLE_SIZE {
+            return None;
+        }
+        table.insert(place, high_score);
+        table.truncate(TABLE_SIZE);
+        Some(place)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn high_score(name: &str, points: u32) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            points,
+        }
+    }
+
+    #[test]
+    fn scores_are_kept_highest_first_with_ties_below() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(
+            high_scores.add(Court::Survival, high_score("a", 5)),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.add(Court::Survival, high_score("b", 9)),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.add(Court::Survival, high_score("c", 5)),
+            Some(2)
+        );
+        let names: Vec<&str> = high_scores
+            .table(Court::Survival)
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["b", "a", "c"]);
+        assert!(high_scores.table(Court::Breakout).is_empty());
+    }
+
+    #[test]
+    fn full_tables_drop_the_lowest_score() {
+        let mut high_scores = HighScores::default();
+        for points in 1..=TABLE_SIZE as u32 {
+            high_scores.add(Court::TimeAttack, high_score("old", points * 10));
+        }
+        // Ties with the last place don't make it into a full table.
+        assert_eq!(
+            high_scores.add(Court::TimeAttack, high_score("tie", 10)),
+            None
+        );
+        assert_eq!(
+            high_scores.add(Court::TimeAttack, high_score("new", 15)),
+            Some(TABLE_SIZE - 1)
+        );
+        let table = high_scores.table(Court::TimeAttack);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table.last().unwrap(), &high_score("new", 15));
+    }
+
+    #[test]
+    fn courts_without_a_challenge_have_no_table() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.add(Court::Versus, high_score("a", 1)), None);
+        assert!(high_scores.table(Court::Versus).is_empty());
+    }
+}
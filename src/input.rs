@@ -0,0 +1,515 @@
+use crate::simulation::{PaddleInput, MAX_PADDLES};
+
+const STICK_DEAD_ZONE: f32 = 0.15;
+// How far the stick has to be pushed to count as a d-pad press in the menu.
+const STICK_PRESS_THRESHOLD: f32 = 0.5;
+// Each player's up and down keys for paddles on the left and right, then their
+// right and left keys for paddles on the top and bottom.
+const PLAYER_KEYS: [[winit::event::VirtualKeyCode; 4]; MAX_PADDLES] = [
+    [
+        winit::event::VirtualKeyCode::W,
+        winit::event::VirtualKeyCode::S,
+        winit::event::VirtualKeyCode::D,
+        winit::event::VirtualKeyCode::A,
+    ],
+    [
+        winit::event::VirtualKeyCode::Up,
+        winit::event::VirtualKeyCode::Down,
+        winit::event::VirtualKeyCode::Right,
+        winit::event::VirtualKeyCode::Left,
+    ],
+    [
+        winit::event::VirtualKeyCode::I,
+        winit::event::VirtualKeyCode::K,
+        winit::event::VirtualKeyCode::L,
+        winit::event::VirtualKeyCode::J,
+    ],
+    [
+        winit::event::VirtualKeyCode::Numpad8,
+        winit::event::VirtualKeyCode::Numpad5,
+        winit::event::VirtualKeyCode::Numpad6,
+        winit::event::VirtualKeyCode::Numpad4,
+    ],
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GamepadButton {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    South,
+    East,
+    Start,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StickAxis {
+    X,
+    Y,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pointer {
+    Mouse,
+    Touch(u64),
+}
+
+// Everything the game reacts to, whichever device it came from. Events can be
+// made up by hand to drive the game without real hardware.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key {
+        keycode: winit::event::VirtualKeyCode,
+        pressed: bool,
+    },
+    GamepadConnected {
+        gamepad: usize,
+        name: String,
+    },
+    GamepadDisconnected {
+        gamepad: usize,
+    },
+    GamepadButton {
+        gamepad: usize,
+        button: GamepadButton,
+        pressed: bool,
+    },
+    // The left stick, from -1 (left or down) to 1 (right or up).
+    GamepadStick {
+        gamepad: usize,
+        axis: StickAxis,
+        value: f32,
+    },
+    // Positions are fractions of the window size, from the top left corner.
+    PointerMoved {
+        pointer: Pointer,
+        x: f32,
+        y: f32,
+    },
+    PointerReleased {
+        pointer: Pointer,
+    },
+}
+
+// A key or button that has just gone down, for menus and playback controls.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Press {
+    Key(winit::event::VirtualKeyCode),
+    Button(GamepadButton),
+}
+
+struct Gamepad {
+    id: usize,
+    name: String,
+    pressed_buttons: Vec<GamepadButton>,
+    stick: [f32; 2],
+}
+
+// Tracks what is held down on every device. Each player gets the first free
+// gamepad when one is connected, and loses it again when it is unplugged.
+pub struct InputState {
+    pressed_keycodes: Vec<winit::event::VirtualKeyCode>,
+    gamepads: Vec<Gamepad>,
+    players: [Option<usize>; MAX_PADDLES],
+    // Most recently moved last.
+    pointers: Vec<(Pointer, [f32; 2])>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            pressed_keycodes: Vec::new(),
+            gamepads: Vec::new(),
+            players: [None; MAX_PADDLES],
+            pointers: Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: InputEvent) -> Option<Press> {
+        match event {
+            InputEvent::Key { keycode, pressed } => {
+                let was_pressed = self.pressed_keycodes.contains(&keycode);
+                self.pressed_keycodes.retain(|&r| r != keycode);
+                if pressed {
+                    self.pressed_keycodes.push(keycode);
+                }
+                // The mouse stays where it was left, so using the keys takes the
+                // paddle back from it until it moves again.
+                let paddle_key = PLAYER_KEYS.iter().any(|keys| keys.contains(&keycode));
+                if pressed && paddle_key {
+                    self.pointers
+                        .retain(|&(pointer, _)| pointer != Pointer::Mouse);
+                }
+                (pressed && !was_pressed).then_some(Press::Key(keycode))
+            }
+            InputEvent::GamepadConnected { gamepad, name } => {
+                self.gamepads.retain(|r| r.id != gamepad);
+                self.gamepads.push(Gamepad {
+                    id: gamepad,
+                    name,
+                    pressed_buttons: Vec::new(),
+                    stick: [0.0; 2],
+                });
+                self.assign_gamepads();
+                None
+            }
+            InputEvent::GamepadDisconnected { gamepad } => {
+                self.gamepads.retain(|r| r.id != gamepad);
+                for (player, assigned) in self.players.iter_mut().enumerate() {
+                    if *assigned == Some(gamepad) {
+                        log::info!("gamepad removed from player {}", player + 1);
+                        *assigned = None;
+                    }
+                }
+                self.assign_gamepads();
+                None
+            }
+            InputEvent::GamepadButton {
+                gamepad,
+                button,
+                pressed,
+            } => {
+                let gamepad = self.gamepads.iter_mut().find(|r| r.id == gamepad)?;
+                let was_pressed = gamepad.pressed_buttons.contains(&button);
+                gamepad.pressed_buttons.retain(|&r| r != button);
+                if pressed {
+                    gamepad.pressed_buttons.push(button);
+                }
+                (pressed && !was_pressed).then_some(Press::Button(button))
+            }
+            InputEvent::GamepadStick {
+                gamepad,
+                axis,
+                value,
+            } => {
+                let gamepad = self.gamepads.iter_mut().find(|r| r.id == gamepad)?;
+                let previous_value = std::mem::replace(&mut gamepad.stick[axis as usize], value);
+                let (positive, negative) = match axis {
+                    StickAxis::X => (GamepadButton::DPadRight, GamepadButton::DPadLeft),
+                    StickAxis::Y => (GamepadButton::DPadUp, GamepadButton::DPadDown),
+                };
+                if value >= STICK_PRESS_THRESHOLD && previous_value < STICK_PRESS_THRESHOLD {
+                    Some(Press::Button(positive))
+                } else if value <= -STICK_PRESS_THRESHOLD && previous_value > -STICK_PRESS_THRESHOLD
+                {
+                    Some(Press::Button(negative))
+                } else {
+                    None
+                }
+            }
+            InputEvent::PointerMoved { pointer, x, y } => {
+                self.pointers.retain(|&(r, _)| r != pointer);
+                self.pointers.push((pointer, [x, y]));
+                None
+            }
+            InputEvent::PointerReleased { pointer } => {
+                self.pointers.retain(|&(r, _)| r != pointer);
+                None
+            }
+        }
+    }
+
+    fn assign_gamepads(&mut self) {
+        for player in 0..self.players.len() {
+            if self.players[player].is_some() {
+                continue;
+            }
+            let players = self.players;
+            let free_gamepad = self
+                .gamepads
+                .iter()
+                .find(|gamepad| !players.contains(&Some(gamepad.id)));
+            if let Some(gamepad) = free_gamepad {
+                log::info!("{} assigned to player {}", gamepad.name, player + 1);
+                self.players[player] = Some(gamepad.id);
+            }
+        }
+    }
+
+    // Keys move the paddle at full speed; the stick moves it in proportion to how
+    // far it is pushed, past a small dead zone. Paddles on the top and bottom use
+    // the horizontal d-pad and stick.
+    pub fn paddle_input(&self, player: usize, horizontal: bool) -> PaddleInput {
+        let keys = PLAYER_KEYS[player];
+        let (positive_key, negative_key) = if horizontal {
+            (keys[2], keys[3])
+        } else {
+            (keys[0], keys[1])
+        };
+        let keys = PaddleInput::from_buttons(
+            self.pressed_keycodes.contains(&positive_key),
+            self.pressed_keycodes.contains(&negative_key),
+        );
+
+        let gamepad = self.players[player]
+            .and_then(|id| self.gamepads.iter().find(|gamepad| gamepad.id == id));
+        let gamepad = match gamepad {
+            Some(gamepad) => gamepad,
+            None => return keys,
+        };
+        let (positive, negative, axis) = if horizontal {
+            (
+                GamepadButton::DPadRight,
+                GamepadButton::DPadLeft,
+                StickAxis::X,
+            )
+        } else {
+            (GamepadButton::DPadUp, GamepadButton::DPadDown, StickAxis::Y)
+        };
+        let dpad = PaddleInput::from_buttons(
+            gamepad.pressed_buttons.contains(&positive),
+            gamepad.pressed_buttons.contains(&negative),
+        );
+        let stick_value = gamepad.stick[axis as usize];
+        let stick = if stick_value.abs() < STICK_DEAD_ZONE {
+            0.0
+        } else {
+            (stick_value.abs() - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE) * stick_value.signum()
+        };
+        keys.max(dpad).max(PaddleInput::from_axis(stick))
+    }
+
+    // Where a pointer wants the player's paddle to be, in court coordinates. With
+    // split zones the left half of the window belongs to the first player and the
+    // right half to the second; otherwise every pointer controls the first player.
+    pub fn pointer_target(&self, player: usize, split_zones: bool) -> Option<[f32; 2]> {
+        self.pointers
+            .iter()
+            .rev()
+            .find(|(_, [x, _])| {
+                let zone = if split_zones && *x >= 0.5 { 1 } else { 0 };
+                zone == player
+            })
+            .map(|(_, [x, y])| [x * 2.0 - 1.0, 1.0 - y * 2.0])
+    }
+}
+
+// Reads gamepads through gilrs, which uses the browser's Gamepad API on the web.
+pub struct GamepadSource {
+    gilrs: Option<gilrs::Gilrs>,
+    pending: Vec<InputEvent>,
+}
+
+impl GamepadSource {
+    pub fn new() -> Self {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(error) => {
+                log::warn!("gamepads are not available: {}", error);
+                None
+            }
+        };
+        // Gamepads that were plugged in before the game started don't send a
+        // connection event of their own.
+        let pending = gilrs
+            .iter()
+            .flat_map(|gilrs| gilrs.gamepads())
+            .map(|(id, gamepad)| InputEvent::GamepadConnected {
+                gamepad: id.into(),
+                name: gamepad.name().to_string(),
+            })
+            .collect();
+        GamepadSource { gilrs, pending }
+    }
+
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = std::mem::take(&mut self.pending);
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return events,
+        };
+
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            let gamepad = id.into();
+            events.push(match event {
+                gilrs::EventType::Connected => InputEvent::GamepadConnected {
+                    gamepad,
+                    name: gilrs.gamepad(id).name().to_string(),
+                },
+                gilrs::EventType::Disconnected => InputEvent::GamepadDisconnected { gamepad },
+                gilrs::EventType::ButtonPressed(button, _) => match gamepad_button(button) {
+                    Some(button) => InputEvent::GamepadButton {
+                        gamepad,
+                        button,
+                        pressed: true,
+                    },
+                    None => continue,
+                },
+                gilrs::EventType::ButtonReleased(button, _) => match gamepad_button(button) {
+                    Some(button) => InputEvent::GamepadButton {
+                        gamepad,
+                        button,
+                        pressed: false,
+                    },
+                    None => continue,
+                },
+                gilrs::EventType::AxisChanged(gilrs::Axis::LeftStickX, value, _) => {
+                    InputEvent::GamepadStick {
+                        gamepad,
+                        axis: StickAxis::X,
+                        value,
+                    }
+                }
+                gilrs::EventType::AxisChanged(gilrs::Axis::LeftStickY, value, _) => {
+                    InputEvent::GamepadStick {
+                        gamepad,
+                        axis: StickAxis::Y,
+                        value,
+                    }
+                }
+                _ => continue,
+            });
+        }
+        events
+    }
+}
+
+fn gamepad_button(button: gilrs::Button) -> Option<GamepadButton> {
+    match button {
+        gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+        gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+        gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+        gilrs::Button::South => Some(GamepadButton::South),
+        gilrs::Button::East => Some(GamepadButton::East),
+        gilrs::Button::Start => Some(GamepadButton::Start),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::VirtualKeyCode;
+
+    fn connect(input: &mut InputState, gamepad: usize) {
+        input.handle(InputEvent::GamepadConnected {
+            gamepad,
+            name: format!("gamepad {}", gamepad),
+        });
+    }
+
+    fn push_stick(
+        input: &mut InputState,
+        gamepad: usize,
+        axis: StickAxis,
+        value: f32,
+    ) -> Option<Press> {
+        input.handle(InputEvent::GamepadStick {
+            gamepad,
+            axis,
+            value,
+        })
+    }
+
+    fn press_key(input: &mut InputState, keycode: VirtualKeyCode, pressed: bool) -> Option<Press> {
+        input.handle(InputEvent::Key { keycode, pressed })
+    }
+
+    #[test]
+    fn stick_ignores_the_dead_zone_and_scales_past_it() {
+        let mut input = InputState::new();
+        connect(&mut input, 0);
+        push_stick(&mut input, 0, StickAxis::Y, STICK_DEAD_ZONE * 0.9);
+        assert_eq!(input.paddle_input(0, false), PaddleInput::default());
+        // The rest of the stick's travel is stretched over the full speed range.
+        push_stick(
+            &mut input,
+            0,
+            StickAxis::Y,
+            STICK_DEAD_ZONE + 0.4 * (1.0 - STICK_DEAD_ZONE),
+        );
+        assert_eq!(input.paddle_input(0, false), PaddleInput::from_axis(0.4));
+        push_stick(&mut input, 0, StickAxis::Y, -1.0);
+        assert_eq!(input.paddle_input(0, false), PaddleInput::from_axis(-1.0));
+        // The vertical stick doesn't move paddles on the top and bottom.
+        assert_eq!(input.paddle_input(0, true), PaddleInput::default());
+        push_stick(&mut input, 0, StickAxis::X, 1.0);
+        assert_eq!(input.paddle_input(0, true), PaddleInput::from_axis(1.0));
+    }
+
+    #[test]
+    fn unplugged_gamepads_free_their_player_for_the_next_one() {
+        let mut input = InputState::new();
+        connect(&mut input, 10);
+        connect(&mut input, 11);
+        push_stick(&mut input, 10, StickAxis::Y, 1.0);
+        push_stick(&mut input, 11, StickAxis::Y, -1.0);
+        assert_eq!(input.paddle_input(0, false), PaddleInput::from_axis(1.0));
+        assert_eq!(input.paddle_input(1, false), PaddleInput::from_axis(-1.0));
+
+        input.handle(InputEvent::GamepadDisconnected { gamepad: 10 });
+        assert_eq!(input.paddle_input(0, false), PaddleInput::default());
+        assert_eq!(input.paddle_input(1, false), PaddleInput::from_axis(-1.0));
+
+        // Plugging it back in starts it afresh with the first free player.
+        connect(&mut input, 10);
+        assert_eq!(input.paddle_input(0, false), PaddleInput::default());
+        push_stick(&mut input, 10, StickAxis::Y, 1.0);
+        assert_eq!(input.paddle_input(0, false), PaddleInput::from_axis(1.0));
+        assert_eq!(input.paddle_input(1, false), PaddleInput::from_axis(-1.0));
+    }
+
+    #[test]
+    fn presses_are_reported_once_until_released() {
+        let mut input = InputState::new();
+        let key = VirtualKeyCode::Return;
+        assert_eq!(press_key(&mut input, key, true), Some(Press::Key(key)));
+        // Key repeat.
+        assert_eq!(press_key(&mut input, key, true), None);
+        assert_eq!(press_key(&mut input, key, false), None);
+        assert_eq!(press_key(&mut input, key, true), Some(Press::Key(key)));
+
+        let button = |pressed| InputEvent::GamepadButton {
+            gamepad: 0,
+            button: GamepadButton::South,
+            pressed,
+        };
+        // Buttons on gamepads that were never connected are ignored.
+        assert_eq!(input.handle(button(true)), None);
+        connect(&mut input, 0);
+        let south = Some(Press::Button(GamepadButton::South));
+        assert_eq!(input.handle(button(true)), south);
+        assert_eq!(input.handle(button(true)), None);
+        assert_eq!(input.handle(button(false)), None);
+        assert_eq!(input.handle(button(true)), south);
+
+        let up = Some(Press::Button(GamepadButton::DPadUp));
+        let down = Some(Press::Button(GamepadButton::DPadDown));
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, 0.6), up);
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, 0.9), None);
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, 0.2), None);
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, 0.6), up);
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, -0.6), down);
+        assert_eq!(push_stick(&mut input, 0, StickAxis::Y, -0.9), None);
+    }
+
+    #[test]
+    fn paddle_keys_take_the_paddle_back_from_the_mouse() {
+        let mut input = InputState::new();
+        input.handle(InputEvent::PointerMoved {
+            pointer: Pointer::Mouse,
+            x: 0.25,
+            y: 0.25,
+        });
+        input.handle(InputEvent::PointerMoved {
+            pointer: Pointer::Touch(1),
+            x: 0.75,
+            y: 0.75,
+        });
+        assert_eq!(input.pointer_target(0, true), Some([-0.5, 0.5]));
+        assert_eq!(input.pointer_target(1, true), Some([0.5, -0.5]));
+
+        press_key(&mut input, VirtualKeyCode::Space, true);
+        assert_eq!(input.pointer_target(0, true), Some([-0.5, 0.5]));
+        press_key(&mut input, VirtualKeyCode::W, true);
+        assert_eq!(input.pointer_target(0, true), None);
+        assert_eq!(
+            input.paddle_input(0, false),
+            PaddleInput::from_buttons(true, false)
+        );
+        // Touches are still held down, so they keep their paddle.
+        assert_eq!(input.pointer_target(1, true), Some([0.5, -0.5]));
+    }
+}
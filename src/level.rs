@@ -0,0 +1,297 @@
+use crate::simulation::PADDLE_HALF_HEIGHT;
+use cgmath::Vector2;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    BadPortal(usize),
+    CourtTooSmall,
+    BadObstacle(usize),
+}
+
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "could not read level: {}", error),
+            LevelError::Parse(error) => write!(f, "could not parse level: {}", error),
+            LevelError::BadPortal(obstacle) => {
+                write!(f, "portal {} does not lead to another portal", obstacle)
+            }
+            LevelError::CourtTooSmall => write!(
+                f,
+                "the court must be at least {} across both ways to fit the paddles",
+                PADDLE_HALF_HEIGHT * 2.0
+            ),
+            LevelError::BadObstacle(obstacle) => write!(
+                f,
+                "obstacle {} needs a positive size and a motion period that isn't negative",
+                obstacle
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<std::io::Error> for LevelError {
+    fn from(error: std::io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
+
+// What happens to a ball that runs into an obstacle. Bumpers bounce the ball
+// back faster by the given share of the serve speed, and portals send it out of
+// the portal at the given index in the level's obstacles, heading the same way.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObstacleKind {
+    Block,
+    Bumper(f32),
+    Portal(usize),
+}
+
+// Moving obstacles swing back and forth between `position - offset` and
+// `position + offset`, taking `period` seconds for the round trip.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Motion {
+    pub offset: [f32; 2],
+    pub period: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    pub position: [f32; 2],
+    pub half_size: [f32; 2],
+    #[serde(default)]
+    pub motion: Option<Motion>,
+}
+
+impl Obstacle {
+    pub fn position_at(&self, seconds: f32) -> Vector2<f32> {
+        let position = Vector2::from(self.position);
+        match self.motion {
+            Some(motion) if motion.period > 0.0 => {
+                let phase = seconds / motion.period * std::f32::consts::TAU;
+                position + Vector2::from(motion.offset) * phase.sin()
+            }
+            _ => position,
+        }
+    }
+}
+
+// Bricks are laid out in rows from the top of the court down, each row centred
+// across it. A digit is a brick taking that many hits to break, anything else a
+// gap.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrickGrid {
+    pub top: f32,
+    pub brick_size: [f32; 2],
+    pub gap: f32,
+    pub rows: Vec<String>,
+}
+
+impl Default for BrickGrid {
+    fn default() -> Self {
+        BrickGrid {
+            top: 0.8,
+            brick_size: [0.16, 0.06],
+            gap: 0.02,
+            rows: [
+                "3333333333",
+                "2222222222",
+                "2222222222",
+                "1111111111",
+                "1111111111",
+            ]
+            .iter()
+            .map(|row| row.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl BrickGrid {
+    pub fn bricks(&self) -> Vec<Brick> {
+        let [width, height] = self.brick_size;
+        let mut bricks = Vec::new();
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let columns = row.chars().count() as f32;
+            let left = -(columns * (width + self.gap) - self.gap) / 2.0;
+            let y = self.top - row_index as f32 * (height + self.gap) - height / 2.0;
+            for (column, cell) in row.chars().enumerate() {
+                if let Some(hits) = cell.to_digit(10).filter(|&hits| hits > 0) {
+                    let x = left + column as f32 * (width + self.gap) + width / 2.0;
+                    bricks.push(Brick {
+                        position: Vector2::new(x, y),
+                        half_size: Vector2::new(width / 2.0, height / 2.0),
+                        hits_left: hits,
+                    });
+                }
+            }
+        }
+        bricks
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Brick {
+    pub position: Vector2<f32>,
+    pub half_size: Vector2<f32>,
+    pub hits_left: u32,
+}
+
+// The court runs from -half_width to half_width and -half_height to
+// half_height, so the default court is 2 by 2 and fills the window. Bigger or
+// narrower courts are shrunk to fit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Level {
+    pub name: String,
+    pub half_width: f32,
+    pub half_height: f32,
+    pub obstacles: Vec<Obstacle>,
+    pub bricks: Option<BrickGrid>,
+}
+
+// For levels that are part of other files, like the settings.
+pub fn valid_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Level, D::Error> {
+    let level = Level::deserialize(deserializer)?;
+    level.validate().map_err(serde::de::Error::custom)?;
+    Ok(level)
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level {
+            name: "Open court".to_string(),
+            half_width: 1.0,
+            half_height: 1.0,
+            obstacles: Vec::new(),
+            bricks: None,
+        }
+    }
+}
+
+impl Level {
+    pub fn from_json(json: &[u8]) -> Result<Self, LevelError> {
+        let level: Level = serde_json::from_slice(json).map_err(LevelError::Parse)?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    // Every side has to be long enough for a paddle to move along it.
+    pub fn validate(&self) -> Result<(), LevelError> {
+        if !(self.half_width >= PADDLE_HALF_HEIGHT && self.half_height >= PADDLE_HALF_HEIGHT) {
+            return Err(LevelError::CourtTooSmall);
+        }
+        for (index, obstacle) in self.obstacles.iter().enumerate() {
+            let [width, height] = obstacle.half_size;
+            let period = obstacle.motion.map_or(0.0, |motion| motion.period);
+            if !(width > 0.0 && height > 0.0 && period >= 0.0) {
+                return Err(LevelError::BadObstacle(index));
+            }
+            if let ObstacleKind::Portal(exit) = obstacle.kind {
+                match self.obstacles.get(exit) {
+                    Some(Obstacle {
+                        kind: ObstacleKind::Portal(_),
+                        ..
+                    }) if exit != index => {}
+                    _ => return Err(LevelError::BadPortal(index)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, LevelError> {
+        Level::from_json(&std::fs::read(path)?)
+    }
+
+    pub fn extent(&self) -> Vector2<f32> {
+        Vector2::new(self.half_width, self.half_height)
+    }
+
+    // How much the court is shrunk by to fit in the window.
+    pub fn view_scale(&self) -> f32 {
+        1.0 / self.half_width.max(self.half_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(json: &str) -> Result<Level, LevelError> {
+        Level::from_json(json.as_bytes())
+    }
+
+    fn obstacle(kind: &str, half_size: &str, period: f32) -> String {
+        format!(
+            r#"{{"kind": {}, "position": [0.0, 0.0], "half_size": {},
+                "motion": {{"offset": [0.0, 0.2], "period": {}}}}}"#,
+            kind, half_size, period
+        )
+    }
+
+    fn obstacles(obstacles: &[String]) -> Result<Level, LevelError> {
+        level(&format!(r#"{{"obstacles": [{}]}}"#, obstacles.join(",")))
+    }
+
+    #[test]
+    fn shipped_levels_are_valid() {
+        level(include_str!("../levels/obstacles.json")).unwrap();
+        level(include_str!("../levels/breakout.json")).unwrap();
+    }
+
+    #[test]
+    fn courts_must_fit_a_paddle_both_ways() {
+        assert!(level(r#"{"half_width": 2.0, "half_height": 0.5}"#).is_ok());
+        let narrow = format!(r#"{{"half_height": {}}}"#, PADDLE_HALF_HEIGHT * 0.9);
+        assert!(matches!(level(&narrow), Err(LevelError::CourtTooSmall)));
+        assert!(matches!(
+            level(r#"{"half_width": 0.0}"#),
+            Err(LevelError::CourtTooSmall)
+        ));
+    }
+
+    #[test]
+    fn obstacles_need_a_size_and_a_period_that_isnt_negative() {
+        let block = obstacle(r#""block""#, "[0.1, 0.1]", 2.0);
+        assert!(obstacles(std::slice::from_ref(&block)).is_ok());
+        assert!(obstacles(&[obstacle(r#""block""#, "[0.1, 0.1]", 0.0)]).is_ok());
+        for bad in [
+            obstacle(r#""block""#, "[0.0, 0.1]", 2.0),
+            obstacle(r#""block""#, "[0.1, -0.1]", 2.0),
+            obstacle(r#""block""#, "[0.1, 0.1]", -1.0),
+        ] {
+            assert!(matches!(
+                obstacles(&[block.clone(), bad]),
+                Err(LevelError::BadObstacle(1))
+            ));
+        }
+    }
+
+    #[test]
+    fn portals_lead_to_another_portal() {
+        let portal =
+            |exit: usize| obstacle(&format!(r#"{{"portal": {}}}"#, exit), "[0.1, 0.1]", 0.0);
+        let block = obstacle(r#""block""#, "[0.1, 0.1]", 0.0);
+        assert!(obstacles(&[portal(1), portal(0)]).is_ok());
+        assert!(matches!(
+            obstacles(&[portal(0)]),
+            Err(LevelError::BadPortal(0))
+        ));
+        assert!(matches!(
+            obstacles(&[portal(1), block.clone()]),
+            Err(LevelError::BadPortal(0))
+        ));
+        assert!(matches!(
+            obstacles(&[block, portal(5)]),
+            Err(LevelError::BadPortal(1))
+        ));
+    }
+}
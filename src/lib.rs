@@ -0,0 +1,7 @@
+// The simulation and everything it needs, for use without the game's window,
+// e.g. to train agents with `env::PongEnv`.
+pub mod env;
+pub mod level;
+pub mod power_up;
+pub mod scene;
+pub mod simulation;
@@ -1,505 +1,892 @@
-use cgmath::{Deg, Vector2};
-use rand::Rng;
-
+mod cli;
+mod display;
 #[cfg(not(target_arch = "wasm32"))]
-const BACKEND_BITS: wgpu::BackendBit = wgpu::BackendBit::PRIMARY;
-#[cfg(target_arch = "wasm32")]
-const BACKEND_BITS: wgpu::BackendBit = wgpu::BackendBit::all();
+mod env_server;
+mod high_scores;
+mod input;
+mod menu;
+mod profiles;
+mod renderer;
+mod replay;
+mod settings;
+mod storage;
+mod text;
+mod tournament;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    color: [f32; 4],
-}
+use cli::{Controller, Options};
+use high_scores::{HighScore, HighScores};
+use input::{GamepadButton, GamepadSource, InputEvent, InputState, Pointer, Press};
+use menu::{Board, Menu, MenuAction, MenuInput};
+use pong_wgpu::{level, power_up, scene, simulation};
+use profiles::{MatchRecord, Profiles};
+use rand::SeedableRng;
+use renderer::{RenderError, Renderer};
+use replay::{Replay, ReplayPlayback};
+use settings::{Settings, SettingsWatcher, VideoMode, WindowMode};
+use simulation::{
+    Court, Difficulty, InputFrame, MatchResult, MatchRules, PaddleInput, Simulation, MAX_PADDLES,
+};
+use text::{Alignment, Text};
+use tournament::Tournament;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct PaddleRaw {
-    position: [f32; 3],
-}
-
-const PADDLE_VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [0.025, -0.15, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [0.025, 0.15, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [-0.025, -0.15, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [-0.025, 0.15, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-];
-
-const BALL_VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [0.025, -0.025, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [0.025, 0.025, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [-0.025, -0.025, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-    Vertex {
-        position: [-0.025, 0.025, 0.0],
-        color: [0.9, 0.9, 0.9, 1.0],
-    },
-];
-
-const RECTANGLE_INDICES: &[u16] = &[0, 1, 2, 3, 2, 1];
-
-struct WgpuState {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    swap_chain_descriptor: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
-    render_pipeline: wgpu::RenderPipeline,
-}
+const HUD_MARGIN: f32 = 24.0;
+const MESSAGE_SECONDS: u64 = 3;
+const SUMMARY_SECONDS: u64 = 8;
 
 struct PongState {
-    wgpu_state: WgpuState,
-    paddle_vertex_buffer: wgpu::Buffer,
-    paddle_instance_data: [PaddleRaw; 2],
-    paddle_instance_buffer: wgpu::Buffer,
-    ball_vertex_buffer: wgpu::Buffer,
-    ball_transform_data: cgmath::Vector2<f32>,
-    ball_transform_buffer: wgpu::Buffer,
-    ball_direction: cgmath::Deg<f32>,
-    rectangle_index_buffer: wgpu::Buffer,
-    pressed_keycodes: Vec<winit::event::VirtualKeyCode>,
-    score_transform_instance_buffer: wgpu::Buffer,
-    
-    score: (u32, u32),
-    ball_speed: f32,
+    renderer: Renderer,
+    input_state: InputState,
+    modifiers: winit::event::ModifiersState,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    minimized: bool,
+    menu: Option<Menu>,
+    high_scores: HighScores,
+    profiles: Profiles,
+    message: Option<(String, instant::Instant)>,
+    // The last match's statistics, shown for a while after it ends.
+    summary: Option<(Vec<String>, instant::Instant)>,
+    quit: bool,
+    window_mode: WindowMode,
+    fullscreen_mode: WindowMode,
+    monitor: Option<usize>,
+    video_mode: Option<VideoMode>,
+
+    settings: Settings,
+    present_mode: wgpu::PresentMode,
+    frame_rate_limit: Option<u32>,
+    controllers: [Controller; MAX_PADDLES],
+    // Profile names for each player from the command line.
+    players: Vec<Option<String>>,
+    difficulty: Difficulty,
+    rules: MatchRules,
+    seed_rng: rand::rngs::StdRng,
+    simulation: Simulation,
+    tick_accumulator: std::time::Duration,
+    recording: Replay,
+    playback: Option<ReplayPlayback>,
+    tournament: Option<Tournament>,
+    // The tournament fixture being played, if the current match is one.
+    fixture: Option<usize>,
 }
 
 impl PongState {
-    async fn new(window: &winit::window::Window) -> Self {
-        let size = window.inner_size();
-        let instance = wgpu::Instance::new(BACKEND_BITS);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Device"),
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
-        let swap_chain_descriptor = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-            format: adapter.get_swap_chain_preferred_format(&surface).unwrap(),
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Mailbox,
-        };
-        let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
-
-        let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader Module"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            flags: wgpu::ShaderFlags::all(),
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pipeline Layout"),
-            bind_group_layouts: &[],
-            push_constant_ranges: &[],
-        });
-
-        let vertex_descriptor = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as u64,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 3]>() as u64,
-                    shader_location: 1,
-                },
-            ],
+    async fn new(
+        window: &winit::window::Window,
+        options: &Options,
+        settings: Settings,
+        playback: Option<ReplayPlayback>,
+    ) -> Result<Self, String> {
+        let renderer = Renderer::new(window, options, settings.theme.clone()).await?;
+
+        let tournament = match &playback {
+            Some(_) => None,
+            None => open_tournament(options),
         };
+        let fixture = tournament.as_ref().and_then(Tournament::next_fixture);
 
-        let paddle_vertex_buffer_raw = bytemuck::cast_slice(PADDLE_VERTICES);
-        let paddle_vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: paddle_vertex_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
-            },
-        );
-
-        let ball_vertex_buffer_raw = bytemuck::cast_slice(BALL_VERTICES);
-        let ball_vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: ball_vertex_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
-            },
-        );
-
-        let ball_transform_data = Vector2::new(0.0, 0.0);
-        let ball_transform_data_raw = [ball_transform_data.x, ball_transform_data.y, 0.0];
-        let ball_transform_buffer_raw = bytemuck::cast_slice(&ball_transform_data_raw);
-        let ball_transform_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: ball_transform_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
-            },
-        );
-
-        let rectangle_index_buffer_raw = bytemuck::cast_slice(RECTANGLE_INDICES);
-        let rectangle_index_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: rectangle_index_buffer_raw,
-                usage: wgpu::BufferUsage::INDEX,
-            },
-        );
-
-        let instance_positions_descriptor = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<PaddleRaw>() as u64,
-            step_mode: wgpu::InputStepMode::Instance,
-            attributes: &[wgpu::VertexAttribute {
-                format: wgpu::VertexFormat::Float32x3,
-                offset: 0,
-                shader_location: 2,
-            }],
+        let mut seed_rng = seed_rng(options);
+        let simulation = match &playback {
+            Some(playback) => playback.replay.simulation(),
+            None => Simulation::new(
+                rand::Rng::gen(&mut seed_rng),
+                match_rules(&options.rules, fixture.is_some()),
+            ),
         };
+        let recording = Replay::new(simulation.seed, simulation.rules.clone());
+        // Replays start playing straight away rather than on the title screen.
+        let menu = match playback {
+            Some(_) => None,
+            None => Some(Menu::title()),
+        };
+        let mut controllers = options.controllers;
+        match_controllers(&mut controllers, &simulation.rules, fixture.is_some());
 
-        let paddle_instance_data = [
-            PaddleRaw {
-                position: [-0.9, 0.0, 0.0],
-            },
-            PaddleRaw {
-                position: [0.9, 0.0, 0.0],
+        Ok(PongState {
+            renderer,
+            input_state: InputState::new(),
+            modifiers: winit::event::ModifiersState::empty(),
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+            minimized: false,
+            menu,
+            high_scores: HighScores::load(),
+            profiles: Profiles::load(),
+            message: None,
+            summary: None,
+            quit: false,
+            window_mode: options.window_mode,
+            fullscreen_mode: match options.window_mode {
+                WindowMode::Windowed => WindowMode::Borderless,
+                window_mode => window_mode,
             },
-        ];
+            monitor: options.monitor,
+            video_mode: options.video_mode,
+            settings,
+            present_mode: options.present_mode,
+            frame_rate_limit: options.frame_rate_limit,
+            controllers,
+            players: options.players.clone(),
+            difficulty: options.difficulty,
+            rules: options.rules.clone(),
+            seed_rng,
+            simulation,
+            tick_accumulator: std::time::Duration::from_secs(0),
+            recording,
+            playback,
+            tournament,
+            fixture,
+        })
+    }
 
-        let paddle_instance_buffer_raw = bytemuck::cast_slice(&paddle_instance_data);
-        let paddle_instance_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: paddle_instance_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
-            },
-        );
-
-        let score_transform_instance_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Score Instance Buffer"),
-                contents: &[],
-                usage: wgpu::BufferUsage::VERTEX
-            }
-        );
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "main",
-                buffers: &[vertex_descriptor, instance_positions_descriptor],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: swap_chain_descriptor.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-        });
-
-        PongState {
-            wgpu_state: WgpuState {
-                surface,
-                device,
-                queue,
-                swap_chain_descriptor,
-                swap_chain,
-                render_pipeline,
-            },
-            paddle_vertex_buffer,
-            paddle_instance_data,
-            paddle_instance_buffer,
-            ball_vertex_buffer,
-            ball_transform_data,
-            ball_transform_buffer,
-            ball_direction: cgmath::Deg(80.0),
-            rectangle_index_buffer,
-            pressed_keycodes: Vec::new(),
-            score_transform_instance_buffer,
-            score: (0, 0),
-            ball_speed: 1.0
+    // Minimizing reports a zero size on some platforms, which can't be used for a
+    // swap chain. The match is paused until the window comes back.
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.minimized = size.width == 0 || size.height == 0;
+        if !self.minimized {
+            self.renderer.resize(size);
         }
     }
 
-    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        let wgpu_state = &mut self.wgpu_state;
-        wgpu_state.swap_chain_descriptor.width = size.width;
-        wgpu_state.swap_chain_descriptor.height = size.height;
-        wgpu_state.swap_chain = wgpu_state
-            .device
-            .create_swap_chain(&wgpu_state.surface, &wgpu_state.swap_chain_descriptor);
+    // Alt+Enter switches between windowed and the last fullscreen mode, F11 cycles
+    // through all of them. The choice is kept for the next launch.
+    fn toggle_window_mode(
+        &mut self,
+        window: &winit::window::Window,
+        input: winit::event::KeyboardInput,
+    ) -> bool {
+        if input.state != winit::event::ElementState::Pressed {
+            return false;
+        }
+        let window_mode = match input.virtual_keycode {
+            Some(winit::event::VirtualKeyCode::Return) if self.modifiers.alt() => {
+                match self.window_mode {
+                    WindowMode::Windowed => self.fullscreen_mode,
+                    _ => WindowMode::Windowed,
+                }
+            }
+            Some(winit::event::VirtualKeyCode::F11) => self.window_mode.next(),
+            _ => return false,
+        };
+        self.set_window_mode(window, window_mode);
+        self.settings.video.window_mode = window_mode;
+        self.settings.save();
+        true
+    }
+
+    fn set_window_mode(&mut self, window: &winit::window::Window, window_mode: WindowMode) {
+        self.window_mode = window_mode;
+        if window_mode != WindowMode::Windowed {
+            self.fullscreen_mode = window_mode;
+        }
+        window.set_fullscreen(display::fullscreen(
+            window,
+            window_mode,
+            self.monitor,
+            self.video_mode,
+        ));
+    }
+
+    // Rules only take effect from the next match, so a running match (and its
+    // replay) keeps the rules it started with. Only what changed in the settings
+    // is applied, so options given on the command line stay until the same
+    // setting is changed.
+    fn apply_settings(&mut self, window: &winit::window::Window, settings: Settings) {
+        let video = &settings.video;
+        let previous = self.settings.video.clone();
+        if (video.window_mode, video.monitor, video.video_mode)
+            != (previous.window_mode, previous.monitor, previous.video_mode)
+        {
+            if video.monitor != previous.monitor {
+                self.monitor = video.monitor;
+            }
+            if video.video_mode != previous.video_mode {
+                self.video_mode = video.video_mode;
+            }
+            let window_mode = if video.window_mode != previous.window_mode {
+                video.window_mode
+            } else {
+                self.window_mode
+            };
+            self.set_window_mode(window, window_mode);
+        }
+        if (video.width, video.height) != (previous.width, previous.height) {
+            window.set_inner_size(winit::dpi::LogicalSize::new(video.width, video.height));
+        }
+        if settings.theme != self.settings.theme {
+            self.renderer.set_theme(settings.theme.clone());
+        }
+        if settings.video.present_mode != self.settings.video.present_mode {
+            self.present_mode = settings.video.present_mode.into();
+            self.renderer.set_present_mode(self.present_mode);
+        }
+
+        if settings.video.frame_rate_limit != self.settings.video.frame_rate_limit {
+            self.frame_rate_limit = settings.video.frame_rate_limit;
+        }
+        if settings.difficulty != self.settings.difficulty {
+            self.difficulty = settings.difficulty;
+        }
+        apply_changed_rules(&mut self.rules, &self.settings.rules, &settings.rules);
+        self.settings = settings;
     }
 
     fn update(&mut self, delta_time: std::time::Duration) {
-        let mut changed = false;
+        if self.minimized {
+            return;
+        }
 
-        let movement_amount = delta_time.as_millis() as f32 * 0.002;
+        let speed = match &self.playback {
+            _ if self.menu.is_some() => 0.0,
+            Some(playback) if playback.paused => 0.0,
+            Some(playback) => playback.speed,
+            None => 1.0,
+        };
 
-        if self
-            .pressed_keycodes
-            .contains(&winit::event::VirtualKeyCode::Up)
-        {
-            self.paddle_instance_data[1].position[1] += movement_amount;
-            changed = true;
+        // Long stalls (dragging the window, a slow frame) are not caught up on,
+        // so the simulation never runs hundreds of ticks at once.
+        let delta_time = delta_time.min(std::time::Duration::from_millis(250));
+        self.tick_accumulator += delta_time.mul_f32(speed);
+        let tick_duration = simulation::tick_duration();
+        while self.tick_accumulator >= tick_duration {
+            self.tick_accumulator -= tick_duration;
+            self.tick();
         }
-        if self
-            .pressed_keycodes
-            .contains(&winit::event::VirtualKeyCode::Down)
-        {
-            self.paddle_instance_data[1].position[1] -= movement_amount;
-            changed = true;
+
+        self.renderer.update(&self.simulation);
+        let menu_texts = match &self.menu {
+            Some(menu) => menu.texts(
+                self.renderer.viewport(),
+                &self.settings,
+                self.controllers(),
+                self.board().as_ref(),
+            ),
+            None => Vec::new(),
+        };
+        self.renderer.set_text(&self.hud_texts(), &menu_texts);
+    }
+
+    fn hud_texts(&self) -> Vec<Text> {
+        let size = self.renderer.viewport().logical_size();
+        let mut texts = Vec::new();
+
+        if let Some((message, shown_at)) = &self.message {
+            if shown_at.elapsed() < std::time::Duration::from_secs(MESSAGE_SECONDS) {
+                texts.push(Text::new(message, size.width / 2.0, size.height / 3.0).scaled(5.0));
+            }
         }
-        if self
-            .pressed_keycodes
-            .contains(&winit::event::VirtualKeyCode::W)
-        {
-            self.paddle_instance_data[0].position[1] += movement_amount;
-            changed = true;
+
+        if let Some((lines, shown_at)) = &self.summary {
+            if shown_at.elapsed() < std::time::Duration::from_secs(SUMMARY_SECONDS) {
+                for (i, line) in lines.iter().enumerate() {
+                    let y = HUD_MARGIN * (3.0 + i as f32);
+                    texts.push(
+                        Text::new(line, HUD_MARGIN, y)
+                            .aligned(Alignment::Left)
+                            .scaled(2.0),
+                    );
+                }
+            }
         }
-        if self
-            .pressed_keycodes
-            .contains(&winit::event::VirtualKeyCode::S)
-        {
-            self.paddle_instance_data[0].position[1] -= movement_amount;
-            changed = true;
-        }
-
-        self.paddle_instance_data[1].position[1] =
-            self.paddle_instance_data[1].position[1].clamp(-0.85, 0.85);
-        self.paddle_instance_data[0].position[1] =
-            self.paddle_instance_data[0].position[1].clamp(-0.85, 0.85);
-
-        if changed {
-            self.set_paddle_buffer();
-        }
-
-        let x_change = cgmath::Angle::sin(self.ball_direction) * 0.01 * self.ball_speed;
-        let y_change = cgmath::Angle::cos(self.ball_direction) * 0.01 * self.ball_speed;
-        self.ball_transform_data += cgmath::Vector2::new(x_change, y_change);
-        let ball_transform_data_raw = [self.ball_transform_data.x, self.ball_transform_data.y, 0.0];
-        let ball_transform_buffer_raw = bytemuck::cast_slice(&ball_transform_data_raw);
-        self.ball_transform_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &self.wgpu_state.device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: ball_transform_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
+
+        // Time attack shows the clock, the other challenges the points so far.
+        let status = match self.simulation.time_left() {
+            Some(time_left) => {
+                let seconds = time_left.ceil() as u32;
+                Some(format!("{}:{:02}", seconds / 60, seconds % 60))
+            }
+            None if self.simulation.rules.court.is_challenge() => {
+                Some(self.simulation.points.to_string())
+            }
+            None => None,
+        };
+        if let Some(status) = status {
+            texts.push(Text::new(status, size.width / 2.0, HUD_MARGIN).scaled(3.0));
+        }
+
+        if let Some(playback) = &self.playback {
+            let y = size.height - HUD_MARGIN;
+            let status = if playback.paused {
+                "Paused".to_string()
+            } else {
+                format!("{}x", playback.speed)
+            };
+            texts.push(Text::new("Replay", HUD_MARGIN, y).aligned(Alignment::Left));
+            texts.push(Text::new(status, size.width - HUD_MARGIN, y).aligned(Alignment::Right));
+        }
+        texts
+    }
+
+    fn tick(&mut self) {
+        let input = match &mut self.playback {
+            Some(playback) => match playback.next_frame(&self.simulation) {
+                Some(frame) => frame,
+                None => {
+                    playback.paused = true;
+                    return;
+                }
             },
-        );
+            None => {
+                let input = self.input_frame();
+                self.recording.frames.push(input);
+                input
+            }
+        };
 
-        if self.ball_transform_data.y > 0.975 || self.ball_transform_data.y < -0.975 {
-            self.ball_direction.0 = 180.0 - self.ball_direction.0;
-            PongState::randomize_direction(&mut self.ball_direction);
+        if let Some(result) = self.simulation.step(input) {
+            self.on_match_over(result);
         }
+    }
 
-        let ball_left = self.ball_transform_data.x - 0.025;
-        let ball_right = self.ball_transform_data.x + 0.025;
-        let ball_top = self.ball_transform_data.y + 0.025;
-        let ball_bottom = self.ball_transform_data.y - 0.025;
+    // The controllers of the players in the current match.
+    fn controllers(&self) -> &[Controller] {
+        &self.controllers[..self.simulation.paddles.len()]
+    }
 
-        for paddle_raw in self.paddle_instance_data {
-            if ball_left < paddle_raw.position[0] + 0.025
-                && ball_right > paddle_raw.position[0] - 0.025
-                && ball_bottom < paddle_raw.position[1] + 0.15
-                && ball_top > paddle_raw.position[1] - 0.15
-            {
-                self.ball_direction = -self.ball_direction;
-                PongState::randomize_direction(&mut self.ball_direction);
+    // A pointer only steers a paddle while its keys and gamepad are left alone.
+    // A lone human player can use either of the first two key sets and gamepads.
+    fn input_frame(&self) -> InputFrame {
+        let controllers = self.controllers();
+        let humans = controllers
+            .iter()
+            .filter(|&&controller| controller == Controller::Human)
+            .count();
+        let split_zones = humans == 2 && controllers.len() == 2;
+        let human_input = |player: usize| {
+            let horizontal = self.simulation.paddles[player].side.is_horizontal();
+            let input = if humans == 1 {
+                (0..2)
+                    .map(|other| self.input_state.paddle_input(other, horizontal))
+                    .fold(PaddleInput::default(), PaddleInput::max)
+            } else {
+                self.input_state.paddle_input(player, horizontal)
+            };
+            match self.input_state.pointer_target(player, split_zones) {
+                Some(target) if input.movement == 0 => {
+                    let scale = self.simulation.rules.level.view_scale();
+                    self.simulation
+                        .input_towards(player, cgmath::Vector2::from(target) / scale)
+                }
+                _ => input,
+            }
+        };
+
+        let mut frame = InputFrame::default();
+        for (player, &controller) in controllers.iter().enumerate() {
+            frame.paddles[player] = match controller {
+                Controller::Human => human_input(player),
+                Controller::Ai => self.simulation.ai_input(player, self.difficulty),
+            };
+        }
+        frame
+    }
+
+    // The stats page shows the player profiles. The title screen shows the
+    // tournament standings while there is one, and otherwise the high scores for
+    // the current challenge.
+    fn board(&self) -> Option<Board> {
+        if matches!(&self.menu, Some(menu) if menu.is_stats()) {
+            let mut lines: Vec<String> = self
+                .profiles
+                .ranked()
+                .iter()
+                .take(10)
+                .enumerate()
+                .map(|(place, profile)| {
+                    format!(
+                        "{:>2}. {} {:.0} {}-{} rally {} fastest {:.1}x",
+                        place + 1,
+                        profile.name,
+                        profile.rating,
+                        profile.wins,
+                        profile.losses,
+                        profile.longest_rally,
+                        profile.fastest_return
+                    )
+                })
+                .collect();
+            if lines.is_empty() {
+                lines.push("No profiles yet, see --players".to_string());
             }
+            return Some(Board {
+                heading: "Ratings".to_string(),
+                lines,
+            });
         }
 
-        if self.ball_transform_data.x > 0.975 {
-            self.score.0 += 1;
-            self.on_score();
-        } else if self.ball_transform_data.x < -0.975 {
-            self.score.1 += 1;
-            self.on_score();
+        if let Some(tournament) = &self.tournament {
+            let mut lines: Vec<String> = tournament
+                .standings()
+                .iter()
+                .enumerate()
+                .map(|(place, standing)| {
+                    let name = &tournament.players[standing.player];
+                    format!(
+                        "{}. {} {}-{}",
+                        place + 1,
+                        name,
+                        standing.wins,
+                        standing.losses
+                    )
+                })
+                .collect();
+            if let Some(champion) = tournament.champion() {
+                lines.push(format!("Champion: {}", tournament.players[champion]));
+            } else if let Some(fixture) = tournament.next_fixture() {
+                let [a, b] = tournament.fixtures[fixture].players;
+                let (a, b) = (&tournament.players[a], &tournament.players[b]);
+                lines.push(format!("Next: {} vs {}", a, b));
+            }
+            return Some(Board {
+                heading: "Standings".to_string(),
+                lines,
+            });
         }
 
-        self.ball_speed += delta_time.as_millis() as f32 / 3000.0;
+        let high_scores = self.high_scores.table(self.simulation.rules.court);
+        if high_scores.is_empty() {
+            return None;
+        }
+        Some(Board {
+            heading: "High scores".to_string(),
+            lines: high_scores
+                .iter()
+                .enumerate()
+                .map(|(place, high_score)| {
+                    format!(
+                        "{:>2}. {} {}",
+                        place + 1,
+                        high_score.name,
+                        high_score.points
+                    )
+                })
+                .collect(),
+        })
     }
 
-    fn on_score(&mut self) {
-        self.reset_objects();
+    // Tournament players go by the names they were entered with, which are also
+    // their profiles. Otherwise only people play under a profile.
+    fn profile_name(&self, player: usize) -> Option<String> {
+        if let (Some(tournament), Some(fixture)) = (&self.tournament, self.fixture) {
+            let entrant = tournament.fixtures[fixture].players[player];
+            return Some(tournament.players[entrant].clone());
+        }
+        match self.controllers[player] {
+            Controller::Human => self.players.get(player).cloned().flatten(),
+            Controller::Ai => None,
+        }
+    }
 
-        let mut buffer_data: [[f32; 3]; 10] = [[2.0, 2.0, 0.0]; 10];
-        for i in 0..self.score.0 {
-            buffer_data[i as usize] = [-0.9 + i as f32 / 15.0, 0.85, 0.0];
+    fn player_name(&self, player: usize) -> String {
+        if let Some(name) = self.profile_name(player) {
+            return name;
         }
-        for i in 0..self.score.1 {
-            buffer_data[i as usize + self.score.0 as usize] = [0.9 - i as f32 / 15.0, 0.85, 0.0];
+        let computers = self
+            .controllers()
+            .iter()
+            .filter(|&&controller| controller == Controller::Ai)
+            .count();
+        match self.controllers[player] {
+            Controller::Human => format!("Player {}", player + 1),
+            Controller::Ai if computers == 1 => "Computer".to_string(),
+            Controller::Ai => format!("Computer {}", player + 1),
         }
-        
-        self.score_transform_instance_buffer = wgpu::util::DeviceExt::create_buffer_init(&self.wgpu_state.device, &wgpu::util::BufferInitDescriptor {
-            label: Some("Score Transform Buffer"),
-            contents: bytemuck::cast_slice::<[f32; 3], u8>(&buffer_data),
-            usage: wgpu::BufferUsage::VERTEX
-        });
+    }
 
-        if self.score.0 == 5 || self.score.1 == 5 {
-            self.score = (0, 0);
+    // Teams of one go by their player's name.
+    fn team_name(&self, team: usize) -> String {
+        let players: Vec<usize> = (0..self.simulation.paddles.len())
+            .filter(|&player| self.simulation.paddles[player].team == team)
+            .collect();
+        match players[..] {
+            [player] => self.player_name(player),
+            _ => format!("{:?} team", self.simulation.sides[team]),
         }
     }
 
-    fn reset_objects(&mut self) {
-        self.ball_transform_data = Vector2::new(0.0, 0.0);
-        self.ball_speed = 1.0;
-        self.paddle_instance_data[0].position[1] = 0.0;
-        self.paddle_instance_data[1].position[1] = 0.0;
-        self.set_paddle_buffer();
+    fn summary_lines(&self, result: &MatchResult) -> Vec<String> {
+        let stats = &result.stats;
+        let mut lines = vec![
+            format!(
+                "Rallies: {}, longest {}, average {:.1}",
+                stats.rallies.len(),
+                stats.longest_rally,
+                stats.average_rally()
+            ),
+            format!("Top speed: {:.1}x", stats.max_ball_speed),
+        ];
+        for player in 0..self.simulation.paddles.len() {
+            lines.push(format!(
+                "{}: {} hits, fastest return {:.1}x",
+                self.player_name(player),
+                stats.hits[player],
+                stats.fastest_returns[player]
+            ));
+        }
+        for (team, serves) in stats.serves.iter().enumerate() {
+            lines.push(format!(
+                "{}: {:.0}s in possession, won {} of {} serves received",
+                self.team_name(team),
+                stats.possession_seconds[team],
+                serves.won,
+                serves.received
+            ));
+        }
+        lines
     }
 
-    fn set_paddle_buffer(&mut self) {
-        let paddle_instance_buffer_raw = bytemuck::cast_slice(&self.paddle_instance_data);
-        self.paddle_instance_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &self.wgpu_state.device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: paddle_instance_buffer_raw,
-                usage: wgpu::BufferUsage::VERTEX,
-            },
-        );
+    fn on_match_over(&mut self, result: MatchResult) {
+        log::info!("match over: {}", score_line(&result.score));
+        let court = self.simulation.rules.court;
+        let challenge = court.is_challenge() && self.fixture.is_none();
+        let message = match result.winner {
+            _ if challenge => format!("{} points", result.points),
+            Some(team) => format!("{} wins", self.team_name(team)),
+            None => "Game over".to_string(),
+        };
+        self.message = Some((message, instant::Instant::now()));
+        self.summary = Some((self.summary_lines(&result), instant::Instant::now()));
+
+        if let Some(playback) = &mut self.playback {
+            playback.paused = true;
+            return;
+        }
+
+        let record = MatchRecord {
+            timestamp: profiles::unix_time(),
+            court,
+            players: (0..self.simulation.paddles.len())
+                .map(|player| self.profile_name(player))
+                .collect(),
+            teams: self
+                .simulation
+                .paddles
+                .iter()
+                .map(|paddle| paddle.team)
+                .collect(),
+            winner: result.winner,
+            score: result.score.clone(),
+            longest_rally: result.stats.longest_rally,
+            fastest_returns: result.stats.fastest_returns.clone(),
+        };
+        if record.players.iter().any(Option::is_some) {
+            self.profiles.record(&record);
+            self.profiles.save();
+        }
+        profiles::append_history(&record);
+
+        // Tournament matches go back to the title screen for the standings. A
+        // drawn time attack has no winner to record, so it is played again. Once
+        // there is a champion the tournament is over and play carries on as usual.
+        if let (Some(tournament), Some(fixture)) = (&mut self.tournament, self.fixture) {
+            let message = match result.winner {
+                Some(team) => {
+                    let players = tournament.fixtures[fixture].players;
+                    tournament.record(fixture, players[team], [result.score[0], result.score[1]]);
+                    match tournament.champion() {
+                        Some(champion) => {
+                            Tournament::remove();
+                            Some(format!(
+                                "{} wins the tournament",
+                                tournament.players[champion]
+                            ))
+                        }
+                        None => {
+                            tournament.save();
+                            None
+                        }
+                    }
+                }
+                None => Some("Draw, play again".to_string()),
+            };
+            if let Some(message) = message {
+                self.message = Some((message, instant::Instant::now()));
+            }
+            if tournament.champion().is_some() {
+                self.tournament = None;
+            }
+            self.menu = Some(Menu::title());
+        }
+
+        // Challenges go back to the title screen to show where the score placed.
+        if challenge {
+            let high_score = HighScore {
+                name: self.player_name(0),
+                points: result.points,
+            };
+            if let Some(place) = self.high_scores.add(court, high_score) {
+                log::info!("new high score in place {}", place + 1);
+                self.high_scores.save();
+                self.message = Some(("New high score".to_string(), instant::Instant::now()));
+            }
+            self.menu = Some(Menu::title());
+        }
+
+        // Replays and their statistics go in the data directory, next to the
+        // high scores and profiles.
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(directory) = storage::data_directory() {
+            let timestamp = profiles::unix_time();
+            let path = directory
+                .join("replays")
+                .join(format!("{}.pongreplay", timestamp));
+            match self.recording.save(&path) {
+                Ok(()) => log::info!("saved replay to {}", path.display()),
+                Err(error) => log::error!("could not save replay: {}", error),
+            }
+            let path = path.with_extension("json");
+            match write_summary(&path, &self.simulation, &result) {
+                Ok(()) => log::info!("saved match summary to {}", path.display()),
+                Err(error) => log::error!("could not save match summary: {}", error),
+            }
+        }
+
+        self.start_match();
     }
 
-    fn randomize_direction(direction: &mut Deg<f32>) {
-        direction.0 += (rand::thread_rng().gen::<f32>() - 0.5) * 20.0;
+    fn start_match(&mut self) {
+        self.fixture = self.tournament.as_ref().and_then(Tournament::next_fixture);
+        let seed = rand::Rng::gen(&mut self.seed_rng);
+        let rules = match_rules(&self.rules, self.fixture.is_some());
+        match_controllers(&mut self.controllers, &rules, self.fixture.is_some());
+        self.simulation = Simulation::new(seed, rules);
+        self.recording = Replay::new(self.simulation.seed, self.simulation.rules.clone());
     }
 
-    fn input(&mut self, input: winit::event::KeyboardInput) {
+    fn restart(&mut self) {
+        match &mut self.playback {
+            Some(playback) => {
+                playback.seek(&mut self.simulation, 0);
+                playback.paused = false;
+            }
+            None => self.start_match(),
+        }
+        self.tick_accumulator = std::time::Duration::from_secs(0);
+    }
+
+    fn input(&mut self, window: &winit::window::Window, input: winit::event::KeyboardInput) {
+        if self.toggle_window_mode(window, input) {
+            return;
+        }
         if let Some(keycode) = input.virtual_keycode {
-            if input.state == winit::event::ElementState::Pressed
-                && !self.pressed_keycodes.contains(&keycode)
-            {
-                self.pressed_keycodes.push(keycode);
+            let pressed = input.state == winit::event::ElementState::Pressed;
+            self.handle_input(window, InputEvent::Key { keycode, pressed });
+        }
+    }
+
+    fn handle_input(&mut self, window: &winit::window::Window, event: InputEvent) {
+        if let Some(press) = self.input_state.handle(event) {
+            self.pressed(window, press);
+        }
+    }
+
+    fn pressed(&mut self, window: &winit::window::Window, press: Press) {
+        if self.menu.is_some() {
+            let menu_input = match press {
+                Press::Key(winit::event::VirtualKeyCode::Up)
+                | Press::Key(winit::event::VirtualKeyCode::W)
+                | Press::Button(GamepadButton::DPadUp) => MenuInput::Up,
+                Press::Key(winit::event::VirtualKeyCode::Down)
+                | Press::Key(winit::event::VirtualKeyCode::S)
+                | Press::Button(GamepadButton::DPadDown) => MenuInput::Down,
+                Press::Key(winit::event::VirtualKeyCode::Left)
+                | Press::Key(winit::event::VirtualKeyCode::A)
+                | Press::Button(GamepadButton::DPadLeft) => MenuInput::Left,
+                Press::Key(winit::event::VirtualKeyCode::Right)
+                | Press::Key(winit::event::VirtualKeyCode::D)
+                | Press::Button(GamepadButton::DPadRight) => MenuInput::Right,
+                Press::Key(winit::event::VirtualKeyCode::Return)
+                | Press::Key(winit::event::VirtualKeyCode::Space)
+                | Press::Button(GamepadButton::South) => MenuInput::Select,
+                Press::Key(winit::event::VirtualKeyCode::Escape)
+                | Press::Key(winit::event::VirtualKeyCode::Back)
+                | Press::Button(GamepadButton::East)
+                | Press::Button(GamepadButton::Start) => MenuInput::Back,
+                _ => return,
+            };
+            self.menu_input(window, menu_input);
+            return;
+        }
+        let keycode = match press {
+            Press::Key(winit::event::VirtualKeyCode::Escape)
+            | Press::Button(GamepadButton::Start) => {
+                self.menu = Some(Menu::new());
+                return;
             }
-            if input.state == winit::event::ElementState::Released {
-                let keycode_index = self.pressed_keycodes.iter().position(|&r| r == keycode);
-                if let Some(keycode_index) = keycode_index {
-                    self.pressed_keycodes.remove(keycode_index);
-                }
+            Press::Key(keycode) => keycode,
+            Press::Button(_) => return,
+        };
+
+        let playback = match &mut self.playback {
+            Some(playback) => playback,
+            None => return,
+        };
+
+        let seek_ticks = 5 * simulation::TICKS_PER_SECOND as u64;
+        match keycode {
+            winit::event::VirtualKeyCode::Space => playback.paused = !playback.paused,
+            winit::event::VirtualKeyCode::Left => {
+                let tick = self.simulation.tick.saturating_sub(seek_ticks);
+                playback.seek(&mut self.simulation, tick);
+            }
+            winit::event::VirtualKeyCode::Right => {
+                let tick = self.simulation.tick + seek_ticks;
+                playback.seek(&mut self.simulation, tick);
             }
+            winit::event::VirtualKeyCode::Up => playback.speed = (playback.speed * 2.0).min(16.0),
+            winit::event::VirtualKeyCode::Down => {
+                playback.speed = (playback.speed / 2.0).max(0.125)
+            }
+            _ => {}
         }
     }
 
-    fn render(&self) {
-        let wgpu_state = &self.wgpu_state;
-        let frame = wgpu_state.swap_chain.get_current_frame().unwrap();
-
-        let mut command_encoder =
-            wgpu_state
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Command Encoder"),
-                });
+    // Hovering selects a menu item and clicking activates it. In a match the
+    // paddle follows the mouse.
+    fn cursor_moved(
+        &mut self,
+        window: &winit::window::Window,
+        position: winit::dpi::PhysicalPosition<f64>,
+    ) {
+        self.cursor_position = position;
+        let viewport = self.renderer.viewport();
+        if let Some(menu) = &mut self.menu {
+            let y = position.to_logical(viewport.scale_factor).y;
+            menu.select_at(viewport, y, self.simulation.paddles.len());
+        }
+        self.pointer_moved(window, Pointer::Mouse, position);
+    }
 
-        {
-            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.output.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
+    // Each finger steers the paddle on its half of the screen. Tapping in the
+    // menu works like a click.
+    fn touch(&mut self, window: &winit::window::Window, touch: winit::event::Touch) {
+        let pointer = Pointer::Touch(touch.id);
+        match touch.phase {
+            winit::event::TouchPhase::Started => {
+                let viewport = self.renderer.viewport();
+                let y = touch.location.to_logical(viewport.scale_factor).y;
+                let player_count = self.simulation.paddles.len();
+                let tapped_item = match &mut self.menu {
+                    Some(menu) => menu.select_at(viewport, y, player_count),
+                    None => false,
+                };
+                if tapped_item {
+                    self.menu_input(window, MenuInput::Select);
+                } else {
+                    self.pointer_moved(window, pointer, touch.location);
+                }
+            }
+            winit::event::TouchPhase::Moved => self.pointer_moved(window, pointer, touch.location),
+            winit::event::TouchPhase::Ended | winit::event::TouchPhase::Cancelled => {
+                self.handle_input(window, InputEvent::PointerReleased { pointer })
+            }
+        }
+    }
 
-            render_pass.set_pipeline(&wgpu_state.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.paddle_vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.paddle_instance_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.rectangle_index_buffer.slice(..),
-                wgpu::IndexFormat::Uint16,
-            );
-            render_pass.draw_indexed(0..RECTANGLE_INDICES.len() as u32, 0, 0..2);
+    fn pointer_moved(
+        &mut self,
+        window: &winit::window::Window,
+        pointer: Pointer,
+        position: winit::dpi::PhysicalPosition<f64>,
+    ) {
+        let size = self.renderer.viewport().size;
+        let event = InputEvent::PointerMoved {
+            pointer,
+            x: position.x as f32 / size.width.max(1) as f32,
+            y: position.y as f32 / size.height.max(1) as f32,
+        };
+        self.handle_input(window, event);
+    }
 
-            render_pass.set_vertex_buffer(0, self.ball_vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.ball_transform_buffer.slice(..));
-            render_pass.draw_indexed(0..RECTANGLE_INDICES.len() as u32, 0, 0..1);
-            
-            render_pass.set_vertex_buffer(1, self.score_transform_instance_buffer.slice(..));
-            render_pass.draw_indexed(0..RECTANGLE_INDICES.len() as u32, 0, 0..self.score.0 + self.score.1);
+    fn mouse_input(
+        &mut self,
+        window: &winit::window::Window,
+        state: winit::event::ElementState,
+        button: winit::event::MouseButton,
+    ) {
+        let viewport = self.renderer.viewport();
+        let y = self.cursor_position.to_logical(viewport.scale_factor).y;
+        let player_count = self.simulation.paddles.len();
+        let menu = match &mut self.menu {
+            Some(menu) if state == winit::event::ElementState::Pressed => menu,
+            _ => return,
+        };
+        match button {
+            winit::event::MouseButton::Left if menu.select_at(viewport, y, player_count) => {
+                self.menu_input(window, MenuInput::Select)
+            }
+            winit::event::MouseButton::Right => self.menu_input(window, MenuInput::Back),
+            _ => {}
         }
+    }
 
-        wgpu_state
-            .queue
-            .submit(std::iter::once(command_encoder.finish()));
+    fn menu_input(&mut self, window: &winit::window::Window, input: MenuInput) {
+        let controllers = &self.controllers[..self.simulation.paddles.len()];
+        let action = match &mut self.menu {
+            Some(menu) => menu.input(input, &self.settings, controllers),
+            None => return,
+        };
+        match action {
+            Some(MenuAction::Resume) => self.menu = None,
+            Some(MenuAction::Restart) => {
+                self.restart();
+                self.menu = None;
+            }
+            Some(MenuAction::Quit) => self.quit = true,
+            Some(MenuAction::Settings(settings)) => {
+                settings.save();
+                self.apply_settings(window, *settings);
+            }
+            Some(MenuAction::Controller(player, controller)) => {
+                self.controllers[player] = controller
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(MenuAction::ExportStats) => {
+                let path = storage::data_directory()
+                    .map(|directory| directory.join("stats.csv"))
+                    .ok_or_else(|| "no data directory to export stats to".to_string());
+                let exported = path.and_then(|path| {
+                    export_stats(&self.profiles, &path)?;
+                    Ok(path)
+                });
+                let message = match exported {
+                    Ok(path) => format!("Exported {}", path.display()),
+                    Err(error) => {
+                        log::error!("{}", error);
+                        "Could not export stats".to_string()
+                    }
+                };
+                self.message = Some((message, instant::Instant::now()));
+            }
+            #[cfg(target_arch = "wasm32")]
+            Some(MenuAction::ExportStats) => {}
+            None => {}
+        }
     }
 }
 
-async fn run(event_loop: winit::event_loop::EventLoop<()>, window: winit::window::Window) {
-    let mut state = PongState::new(&window).await;
+async fn run(
+    event_loop: winit::event_loop::EventLoop<()>,
+    window: winit::window::Window,
+    options: Options,
+    settings: Settings,
+    playback: Option<ReplayPlayback>,
+) {
+    let mut state = match PongState::new(&window, &options, settings, playback).await {
+        Ok(state) => state,
+        Err(error) => {
+            log::error!("{}", error);
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            #[cfg(target_arch = "wasm32")]
+            return;
+        }
+    };
+    let mut settings_watcher = SettingsWatcher::new();
+    let mut gamepads = GamepadSource::new();
+    #[cfg(target_arch = "wasm32")]
+    let mut browser_fullscreen = false;
 
     let mut previous_frame_time = instant::Instant::now();
 
@@ -509,33 +896,375 @@ async fn run(event_loop: winit::event_loop::EventLoop<()>, window: winit::window
                 *control_flow = winit::event_loop::ControlFlow::Exit
             }
             winit::event::WindowEvent::Resized(size) => state.resize(size),
-            winit::event::WindowEvent::KeyboardInput { input, .. } => state.input(input),
+            winit::event::WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => {
+                state.renderer.set_scale_factor(scale_factor);
+                state.resize(*new_inner_size);
+            }
+            winit::event::WindowEvent::ModifiersChanged(modifiers) => state.modifiers = modifiers,
+            winit::event::WindowEvent::KeyboardInput { input, .. } => state.input(&window, input),
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                state.cursor_moved(&window, position)
+            }
+            winit::event::WindowEvent::CursorLeft { .. } => state.handle_input(
+                &window,
+                InputEvent::PointerReleased {
+                    pointer: Pointer::Mouse,
+                },
+            ),
+            winit::event::WindowEvent::Touch(touch) => state.touch(&window, touch),
+            winit::event::WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => state.mouse_input(&window, button_state, button),
             _ => {}
         },
-        winit::event::Event::MainEventsCleared => window.request_redraw(),
+        winit::event::Event::MainEventsCleared => {
+            if state.quit {
+                *control_flow = winit::event_loop::ControlFlow::Exit;
+                return;
+            }
+            for event in gamepads.poll() {
+                state.handle_input(&window, event);
+            }
+            if state.minimized {
+                *control_flow = winit::event_loop::ControlFlow::Wait;
+                return;
+            }
+
+            // Leaving fullscreen with the browser's own controls is picked up here.
+            #[cfg(target_arch = "wasm32")]
+            if window.fullscreen().is_some() != browser_fullscreen {
+                browser_fullscreen = !browser_fullscreen;
+                display::fit_canvas(&window, browser_fullscreen, options.width, options.height);
+                if !browser_fullscreen && state.window_mode != WindowMode::Windowed {
+                    state.set_window_mode(&window, WindowMode::Windowed);
+                }
+            }
+
+            if let Some(settings) = settings_watcher.poll() {
+                if settings != state.settings {
+                    log::info!("reloaded settings");
+                    state.apply_settings(&window, settings);
+                }
+            }
+
+            let frame_interval = state
+                .frame_rate_limit
+                .map(|frame_rate_limit| std::time::Duration::from_secs(1) / frame_rate_limit);
+            match frame_interval {
+                Some(frame_interval) if previous_frame_time.elapsed() < frame_interval => {
+                    *control_flow = winit::event_loop::ControlFlow::WaitUntil(
+                        previous_frame_time + frame_interval,
+                    );
+                }
+                _ => {
+                    *control_flow = winit::event_loop::ControlFlow::Poll;
+                    window.request_redraw();
+                }
+            }
+        }
         winit::event::Event::RedrawRequested(_) => {
             let frame_time = instant::Instant::now();
             let delta_time = frame_time - previous_frame_time;
             state.update(delta_time);
-            state.render();
+            if let Err(RenderError::DeviceLost) = state.renderer.render() {
+                log::warn!("graphics device lost, recreating it");
+                recreate_renderer(&window, &options, &mut state);
+            }
             previous_frame_time = frame_time;
         }
         _ => {}
     });
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+fn recreate_renderer(window: &winit::window::Window, options: &Options, state: &mut PongState) {
+    // Keep the present mode picked since starting rather than the one it started with.
+    let options = Options {
+        present_mode: state.present_mode,
+        ..options.clone()
+    };
+    let renderer = Renderer::new(window, &options, state.settings.theme.clone());
+    match pollster::block_on(renderer) {
+        Ok(renderer) => {
+            state.renderer = renderer;
+            state.renderer.update(&state.simulation);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The event loop can't wait on the browser to hand out a new device, so the page
+// is reloaded instead.
+#[cfg(target_arch = "wasm32")]
+fn recreate_renderer(_window: &winit::window::Window, _options: &Options, _state: &mut PongState) {
+    if let Some(window) = web_sys::window() {
+        let _ = window.location().reload();
+    }
+}
+
+// What the analytics tools read for every match, saved next to its replay.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(serde::Serialize)]
+struct MatchSummary<'a> {
+    timestamp: u64,
+    seed: u64,
+    court: Court,
+    ticks: u64,
+    result: &'a MatchResult,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_summary(
+    path: &std::path::Path,
+    simulation: &Simulation,
+    result: &MatchResult,
+) -> Result<(), String> {
+    let summary = MatchSummary {
+        timestamp: profiles::unix_time(),
+        seed: simulation.seed,
+        court: simulation.rules.court,
+        ticks: simulation.tick,
+        result,
+    };
+    let contents = serde_json::to_string_pretty(&summary).map_err(|error| error.to_string())?;
+    path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(path, contents))
+        .map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn score_line(score: &[u32]) -> String {
+    let score: Vec<String> = score.iter().map(|score| score.to_string()).collect();
+    score.join(" - ")
+}
+
+fn seed_rng(options: &Options) -> rand::rngs::StdRng {
+    match options.seed {
+        Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+        None => rand::rngs::StdRng::from_entropy(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_replay(path: &str) -> ReplayPlayback {
+    match Replay::load(std::path::Path::new(path)) {
+        Ok(replay) => ReplayPlayback::new(replay),
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn apply_changed_rules(rules: &mut MatchRules, old: &MatchRules, new: &MatchRules) {
+    macro_rules! apply_changed {
+        ($($rule:ident),*) => {
+            $(
+                if new.$rule != old.$rule {
+                    rules.$rule = new.$rule.clone();
+                }
+            )*
+        };
+    }
+    apply_changed!(court, win_score, lives, time_limit, paddle, ball, multiball, power_ups, level);
+}
+
+// Tournament matches are one against one, so courts for more or fewer players
+// are swapped for versus.
+fn match_rules(rules: &MatchRules, tournament_match: bool) -> MatchRules {
+    let mut rules = rules.clone();
+    if tournament_match && !matches!(rules.court, Court::Versus | Court::TimeAttack) {
+        rules.court = Court::Versus;
+    }
+    rules
+}
+
+// Challenges are the first player's alone, so anyone else on the court is the
+// computer. Both players in a tournament match are people.
+fn match_controllers(
+    controllers: &mut [Controller; MAX_PADDLES],
+    rules: &MatchRules,
+    tournament_match: bool,
+) {
+    if tournament_match {
+        controllers[..2].fill(Controller::Human);
+    } else if rules.court.is_challenge() {
+        controllers[1..].fill(Controller::Ai);
+    }
+}
+
+// A new tournament replaces any saved one straight away, so it can be resumed
+// even if the first match is never finished.
+fn open_tournament(options: &Options) -> Option<Tournament> {
+    if let Some(players) = &options.tournament {
+        let tournament = Tournament::new(options.tournament_format, players.clone());
+        tournament.save();
+        return Some(tournament);
+    }
+    if !options.resume_tournament {
+        return None;
+    }
+    match Tournament::load() {
+        Ok(Some(tournament)) if tournament.champion().is_some() => {
+            log::error!("the saved tournament is already finished");
+            Tournament::remove();
+            None
+        }
+        Ok(Some(tournament)) => Some(tournament),
+        Ok(None) => {
+            log::error!("there is no tournament to resume");
+            None
+        }
+        Err(error) => {
+            log::error!("could not load tournament: {}", error);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn export_stats(profiles: &Profiles, path: &std::path::Path) -> Result<(), String> {
+    path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(path, profiles.to_csv()))
+        .map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+// Without a window both paddles are played by the computer, unless a replay is given.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_headless(options: &Options, playback: Option<ReplayPlayback>) {
+    let mut simulation = match &playback {
+        Some(playback) => playback.replay.simulation(),
+        None => Simulation::new(
+            rand::Rng::gen(&mut seed_rng(options)),
+            options.rules.clone(),
+        ),
+    };
+
+    let result = loop {
+        let input = match &playback {
+            Some(playback) => match playback.next_frame(&simulation) {
+                Some(frame) => frame,
+                None => break None,
+            },
+            None => {
+                let mut frame = InputFrame::default();
+                for player in 0..simulation.paddles.len() {
+                    frame.paddles[player] = simulation.ai_input(player, options.difficulty);
+                }
+                frame
+            }
+        };
+        if let Some(result) = simulation.step(input) {
+            break Some(result);
+        }
+        // Computers can keep a rally going forever once the ball is at its top
+        // speed, so long matches are called a draw.
+        if playback.is_none() && options.max_ticks > 0 && simulation.tick >= options.max_ticks {
+            break Some(simulation.draw());
+        }
+    };
+
+    match result {
+        Some(result) => {
+            let outcome = match result.winner {
+                Some(_) => score_line(&result.score),
+                None => format!("Draw at {}", score_line(&result.score)),
+            };
+            println!("{} after {} ticks", outcome, simulation.tick);
+            if let Some(path) = &options.summary {
+                if let Err(error) = write_summary(std::path::Path::new(path), &simulation, &result)
+                {
+                    eprintln!("could not save match summary: {}", error);
+                }
+            }
+        }
+        None => println!(
+            "replay ended after {} ticks without a result",
+            simulation.tick
+        ),
+    }
+}
+
 fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    let (settings, options) = {
+        env_logger::init();
+        let settings = Settings::load();
+        let options = Options::from_args(Options::from_settings(&settings));
+        (settings, options)
+    };
+    #[cfg(target_arch = "wasm32")]
+    let (settings, options) = {
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        console_log::init().expect("could not initialize logger");
+        let settings = Settings::load();
+        let options = Options::from_query(Options::from_settings(&settings));
+        (settings, options)
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let playback = options.replay.as_deref().map(load_replay);
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.list_adapters {
+        renderer::list_adapters();
+        return;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &options.export_stats {
+        if let Err(error) = export_stats(&Profiles::load(), std::path::Path::new(path)) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.env {
+        env_server::run(&options);
+        return;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.headless {
+        run_headless(&options, playback);
+        return;
+    }
+
     let event_loop = winit::event_loop::EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.list_monitors {
+        display::list_monitors(&event_loop);
+        return;
+    }
+    let window = winit::window::WindowBuilder::new()
+        .with_title("Pong")
+        .with_inner_size(winit::dpi::LogicalSize::new(options.width, options.height))
+        .build(&event_loop)
+        .unwrap();
+    // Fullscreen is entered once the window exists, so the monitor it opened on
+    // can be used. In the browser this waits for the first key press or click.
+    window.set_fullscreen(display::fullscreen(
+        &window,
+        options.window_mode,
+        options.monitor,
+        options.video_mode,
+    ));
     #[cfg(not(target_arch = "wasm32"))]
     {
-        env_logger::init();
-        pollster::block_on(run(event_loop, window));
+        pollster::block_on(run(event_loop, window, options, settings, playback));
     }
     #[cfg(target_arch = "wasm32")]
     {
-        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-        console_log::init().expect("could not initialize logger");
+        if options.replay.is_some() || options.headless {
+            log::warn!("replays and headless mode are not available in the browser");
+        }
         use winit::platform::web::WindowExtWebSys;
         // On wasm, append the canvas to the document body
         web_sys::window()
@@ -546,6 +1275,6 @@ fn main() {
                     .ok()
             })
             .expect("couldn't append canvas to document body");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+        wasm_bindgen_futures::spawn_local(run(event_loop, window, options, settings, None));
     }
 }
@@ -0,0 +1,311 @@
+use crate::cli::Controller;
+use crate::renderer::Viewport;
+use crate::settings::{PresentMode, Settings, WindowMode};
+use crate::simulation::Difficulty;
+use crate::text::Text;
+
+const LINE_HEIGHT: f32 = 36.0;
+const FRAME_RATE_LIMITS: &[Option<u32>] =
+    &[None, Some(30), Some(60), Some(120), Some(144), Some(240)];
+
+// Keyboard, mouse and gamepad all navigate the menu through these.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+// Shown under the items on the title screen, e.g. high scores or tournament
+// standings, and on the stats page.
+pub struct Board {
+    pub heading: String,
+    pub lines: Vec<String>,
+}
+
+pub enum MenuAction {
+    Resume,
+    Restart,
+    Quit,
+    Settings(Box<Settings>),
+    Controller(usize, Controller),
+    ExportStats,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Page {
+    Title,
+    Main,
+    Settings,
+    Stats,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Item {
+    Play,
+    Resume,
+    Restart,
+    Settings,
+    Stats,
+    ExportStats,
+    Quit,
+    // Every player but the first, who is always human.
+    Controller(usize),
+    WindowMode,
+    PresentMode,
+    FrameRateLimit,
+    Difficulty,
+    Back,
+}
+
+// The settings page goes back to whichever page the menu was opened on: the
+// title screen before a match or the pause menu during one. The stats page is
+// only on the title screen.
+pub struct Menu {
+    page: Page,
+    home: Page,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Menu {
+            page: Page::Main,
+            home: Page::Main,
+            selected: 0,
+        }
+    }
+
+    pub fn title() -> Self {
+        Menu {
+            page: Page::Title,
+            home: Page::Title,
+            selected: 0,
+        }
+    }
+
+    fn items(&self, player_count: usize) -> Vec<Item> {
+        match self.page {
+            // There is nothing to quit to in the browser.
+            #[cfg(not(target_arch = "wasm32"))]
+            Page::Title => vec![Item::Play, Item::Stats, Item::Settings, Item::Quit],
+            #[cfg(target_arch = "wasm32")]
+            Page::Title => vec![Item::Play, Item::Stats, Item::Settings],
+            #[cfg(not(target_arch = "wasm32"))]
+            Page::Main => vec![Item::Resume, Item::Restart, Item::Settings, Item::Quit],
+            #[cfg(target_arch = "wasm32")]
+            Page::Main => vec![Item::Resume, Item::Restart, Item::Settings],
+            Page::Settings => (1..player_count)
+                .map(Item::Controller)
+                .chain([
+                    Item::WindowMode,
+                    Item::PresentMode,
+                    Item::FrameRateLimit,
+                    Item::Difficulty,
+                    Item::Back,
+                ])
+                .collect(),
+            // Exporting writes a file, which the browser has nowhere to put.
+            #[cfg(not(target_arch = "wasm32"))]
+            Page::Stats => vec![Item::ExportStats, Item::Back],
+            #[cfg(target_arch = "wasm32")]
+            Page::Stats => vec![Item::Back],
+        }
+    }
+
+    pub fn is_stats(&self) -> bool {
+        self.page == Page::Stats
+    }
+
+    pub fn input(
+        &mut self,
+        input: MenuInput,
+        settings: &Settings,
+        controllers: &[Controller],
+    ) -> Option<MenuAction> {
+        let items = self.items(controllers.len());
+        let item_count = items.len();
+        let item = items[self.selected];
+        match input {
+            MenuInput::Up => self.selected = (self.selected + item_count - 1) % item_count,
+            MenuInput::Down => self.selected = (self.selected + 1) % item_count,
+            MenuInput::Back if self.page == self.home => return Some(MenuAction::Resume),
+            MenuInput::Back => self.open(self.home, controllers.len()),
+            MenuInput::Select => match item {
+                Item::Play | Item::Resume => return Some(MenuAction::Resume),
+                Item::Restart => return Some(MenuAction::Restart),
+                Item::Quit => return Some(MenuAction::Quit),
+                Item::Settings => self.open(Page::Settings, controllers.len()),
+                Item::Stats => self.open(Page::Stats, controllers.len()),
+                Item::ExportStats => return Some(MenuAction::ExportStats),
+                Item::Back => self.open(self.home, controllers.len()),
+                _ => return Menu::change(item, 1, settings, controllers),
+            },
+            MenuInput::Left => return Menu::change(item, -1, settings, controllers),
+            MenuInput::Right => return Menu::change(item, 1, settings, controllers),
+        }
+        None
+    }
+
+    // Going back from the settings or stats selects the item that led to them.
+    fn open(&mut self, page: Page, player_count: usize) {
+        let previous_item = match self.page {
+            Page::Settings => Some(Item::Settings),
+            Page::Stats => Some(Item::Stats),
+            Page::Title | Page::Main => None,
+        };
+        self.page = page;
+        self.selected = self
+            .items(player_count)
+            .iter()
+            .position(|&item| Some(item) == previous_item)
+            .unwrap_or(0);
+    }
+
+    fn change(
+        item: Item,
+        step: isize,
+        settings: &Settings,
+        controllers: &[Controller],
+    ) -> Option<MenuAction> {
+        let mut settings = settings.clone();
+        match item {
+            Item::Controller(player) => {
+                let controller = match controllers[player] {
+                    Controller::Human => Controller::Ai,
+                    Controller::Ai => Controller::Human,
+                };
+                return Some(MenuAction::Controller(player, controller));
+            }
+            Item::WindowMode => {
+                settings.video.window_mode = cycle(
+                    &[
+                        WindowMode::Windowed,
+                        WindowMode::Borderless,
+                        WindowMode::Exclusive,
+                    ],
+                    settings.video.window_mode,
+                    step,
+                )
+            }
+            Item::PresentMode => {
+                settings.video.present_mode = cycle(
+                    &[
+                        PresentMode::Fifo,
+                        PresentMode::Mailbox,
+                        PresentMode::Immediate,
+                    ],
+                    settings.video.present_mode,
+                    step,
+                )
+            }
+            Item::FrameRateLimit => {
+                settings.video.frame_rate_limit =
+                    cycle(FRAME_RATE_LIMITS, settings.video.frame_rate_limit, step)
+            }
+            Item::Difficulty => {
+                settings.difficulty = cycle(
+                    &[Difficulty::Easy, Difficulty::Normal, Difficulty::Hard],
+                    settings.difficulty,
+                    step,
+                )
+            }
+            _ => return None,
+        }
+        Some(MenuAction::Settings(Box::new(settings)))
+    }
+
+    pub fn select_at(&mut self, viewport: Viewport, y: f32, player_count: usize) -> bool {
+        let item_count = self.items(player_count).len();
+        let first_item_y = Menu::first_line_y(viewport, item_count) + LINE_HEIGHT;
+        let index = ((y - first_item_y) / LINE_HEIGHT + 0.5).floor();
+        if index >= 0.0 && (index as usize) < item_count {
+            self.selected = index as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    // The title line followed by one line per item, centred on the window.
+    fn first_line_y(viewport: Viewport, item_count: usize) -> f32 {
+        viewport.logical_size().height / 2.0 - item_count as f32 * LINE_HEIGHT / 2.0
+    }
+
+    pub fn texts(
+        &self,
+        viewport: Viewport,
+        settings: &Settings,
+        controllers: &[Controller],
+        board: Option<&Board>,
+    ) -> Vec<Text> {
+        let items = self.items(controllers.len());
+        let x = viewport.logical_size().width / 2.0;
+        let first_line_y = Menu::first_line_y(viewport, items.len());
+        let title = match self.page {
+            Page::Title => "Pong",
+            Page::Main => "Paused",
+            Page::Settings => "Settings",
+            Page::Stats => "Stats",
+        };
+
+        let mut texts = vec![Text::new(title, x, first_line_y).scaled(4.0)];
+        for (i, &item) in items.iter().enumerate() {
+            let label = Menu::label(item, settings, controllers);
+            let y = first_line_y + (i + 1) as f32 * LINE_HEIGHT;
+            texts.push(if i == self.selected {
+                Text::new(format!("> {} <", label), x, y).colored(settings.theme.highlight)
+            } else {
+                Text::new(label, x, y)
+            });
+        }
+
+        if let Some(board) = board.filter(|_| matches!(self.page, Page::Title | Page::Stats)) {
+            let mut y = first_line_y + (items.len() + 2) as f32 * LINE_HEIGHT;
+            texts.push(Text::new(&board.heading, x, y).colored(settings.theme.highlight));
+            for line in &board.lines {
+                y += LINE_HEIGHT * 0.75;
+                texts.push(Text::new(line, x, y).scaled(2.0));
+            }
+        }
+        texts
+    }
+
+    fn label(item: Item, settings: &Settings, controllers: &[Controller]) -> String {
+        match item {
+            Item::Play => "Play".to_string(),
+            Item::Resume => "Resume".to_string(),
+            Item::Restart => "Restart".to_string(),
+            Item::Settings => "Settings".to_string(),
+            Item::Stats => "Stats".to_string(),
+            Item::ExportStats => "Export CSV".to_string(),
+            Item::Quit => "Quit".to_string(),
+            Item::Controller(player) => match controllers[player] {
+                Controller::Human => format!("Player {}: Human", player + 1),
+                Controller::Ai => format!("Player {}: Computer", player + 1),
+            },
+            Item::WindowMode => format!("Window: {:?}", settings.video.window_mode),
+            Item::PresentMode => format!("Present mode: {:?}", settings.video.present_mode),
+            Item::FrameRateLimit => match settings.video.frame_rate_limit {
+                Some(frame_rate_limit) => format!("Frame rate limit: {}", frame_rate_limit),
+                None => "Frame rate limit: Off".to_string(),
+            },
+            Item::Difficulty => format!("Difficulty: {:?}", settings.difficulty),
+            Item::Back => "Back".to_string(),
+        }
+    }
+}
+
+// Steps through a list of choices, wrapping around at either end. Values that
+// aren't in the list (e.g. a hand-edited frame rate limit) start from the first.
+fn cycle<T: Copy + PartialEq>(choices: &[T], current: T, step: isize) -> T {
+    let count = choices.len() as isize;
+    let index = match choices.iter().position(|&choice| choice == current) {
+        Some(index) => (index as isize + step).rem_euclid(count),
+        None => 0,
+    };
+    choices[index as usize]
+}
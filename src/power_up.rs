@@ -0,0 +1,121 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+// How far power-ups can scale paddles and balls, so a typo in the rules can't
+// make a paddle vanish or a ball skip through everything.
+const MIN_SCALE: f32 = 0.1;
+const MAX_SCALE: f32 = 4.0;
+
+// What a power-up does while it lasts. New power-ups are made by combining these
+// in the rules, e.g. a huge paddle is `{ "paddle_size": 2.0 }`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    // Multiplies the length of the affected teams' paddles.
+    PaddleSize(f32),
+    // Multiplies how fast balls last hit by the affected teams move.
+    BallSpeed(f32),
+    // Serves this many extra balls straight away; it has no duration.
+    ExtraBalls(u32),
+    // Balls last hit by the affected teams turn this many degrees a second.
+    Curve(f32),
+    // The affected teams' goals turn into walls.
+    Shield,
+    // Hides balls last hit by the affected teams.
+    InvisibleBall,
+}
+
+// Who an effect applies to, relative to the team whose hit collected it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Collector,
+    Opponents,
+    Everyone,
+}
+
+impl Target {
+    pub fn includes(self, collector: usize, team: usize) -> bool {
+        match self {
+            Target::Collector => team == collector,
+            Target::Opponents => team != collector,
+            Target::Everyone => true,
+        }
+    }
+}
+
+// Durations are in seconds. Pickups are chosen at random in proportion to their
+// weight.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerUp {
+    pub name: String,
+    pub effect: Effect,
+    pub target: Target,
+    pub duration: f32,
+    pub weight: u32,
+}
+
+// A pickup appears somewhere in the middle of the court every `spawn_interval`
+// seconds, up to `max_pickups` at once. An interval of 0 turns power-ups off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerUpRules {
+    pub spawn_interval: f32,
+    pub max_pickups: u32,
+    #[serde(deserialize_with = "valid_power_ups")]
+    pub power_ups: Vec<PowerUp>,
+}
+
+impl Default for PowerUpRules {
+    fn default() -> Self {
+        PowerUpRules {
+            spawn_interval: 0.0,
+            max_pickups: 2,
+            power_ups: serde_json::from_str(include_str!("power_ups.json"))
+                .expect("built-in power-ups are valid"),
+        }
+    }
+}
+
+// Rejects power-ups that scale things by a factor out of range or last a
+// negative time, wherever the rules are read from.
+fn valid_power_ups<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<PowerUp>, D::Error> {
+    let power_ups = Vec::<PowerUp>::deserialize(deserializer)?;
+    for power_up in &power_ups {
+        if let Effect::PaddleSize(scale) | Effect::BallSpeed(scale) = power_up.effect {
+            if !(MIN_SCALE..=MAX_SCALE).contains(&scale) {
+                return Err(serde::de::Error::custom(format!(
+                    "power-up '{}' scales by {}, which is not between {} and {}",
+                    power_up.name, scale, MIN_SCALE, MAX_SCALE
+                )));
+            }
+        }
+        if power_up.duration < 0.0 {
+            return Err(serde::de::Error::custom(format!(
+                "power-up '{}' has a negative duration",
+                power_up.name
+            )));
+        }
+    }
+    Ok(power_ups)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Pickup {
+    pub position: cgmath::Vector2<f32>,
+    pub power_up: usize,
+}
+
+// A collected power-up that is still running.
+#[derive(Copy, Clone, Debug)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    pub target: Target,
+    pub collector: usize,
+    pub remaining_ticks: u64,
+}
+
+impl ActiveEffect {
+    pub fn applies_to(&self, team: usize) -> bool {
+        self.target.includes(self.collector, team)
+    }
+}
@@ -0,0 +1,264 @@
+use crate::simulation::Court;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+
+const PROFILES_NAME: &str = "profiles";
+const HISTORY_NAME: &str = "history";
+const STARTING_RATING: f32 = 1500.0;
+const RATING_K_FACTOR: f32 = 32.0;
+
+// Rallies are in paddle hits and the fastest return is a multiple of the serve
+// speed, as in the match results.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub rating: f32,
+    pub wins: u32,
+    pub losses: u32,
+    pub longest_rally: u32,
+    pub fastest_return: f32,
+}
+
+impl Profile {
+    fn new(name: &str) -> Self {
+        Profile {
+            name: name.to_string(),
+            rating: STARTING_RATING,
+            wins: 0,
+            losses: 0,
+            longest_rally: 0,
+            fastest_return: 0.0,
+        }
+    }
+}
+
+// One finished match. Players are listed per paddle with the team they played
+// for; only players with a profile have a name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub timestamp: u64,
+    pub court: Court,
+    pub players: Vec<Option<String>>,
+    pub teams: Vec<usize>,
+    pub winner: Option<usize>,
+    pub score: Vec<u32>,
+    pub longest_rally: u32,
+    pub fastest_returns: Vec<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    pub fn load() -> Self {
+        match storage::load(PROFILES_NAME) {
+            Ok(profiles) => profiles.unwrap_or_default(),
+            Err(error) => {
+                log::error!("could not load profiles: {}", error);
+                Profiles::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(PROFILES_NAME, self) {
+            log::error!("could not save profiles: {}", error);
+        }
+    }
+
+    fn profile_mut(&mut self, name: &str) -> &mut Profile {
+        let index = match self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.profiles.push(Profile::new(name));
+                self.profiles.len() - 1
+            }
+        };
+        &mut self.profiles[index]
+    }
+
+    // Everyone with a profile gets the win or loss and their records. The
+    // ratings only change in one against one matches between two profiles, using
+    // Elo.
+    pub fn record(&mut self, record: &MatchRecord) {
+        let winner = match record.winner {
+            Some(winner) => winner,
+            None => return,
+        };
+        for (player, name) in record.players.iter().enumerate() {
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            let profile = self.profile_mut(name);
+            if record.teams[player] == winner {
+                profile.wins += 1;
+            } else {
+                profile.losses += 1;
+            }
+            profile.longest_rally = profile.longest_rally.max(record.longest_rally);
+            profile.fastest_return = profile.fastest_return.max(record.fastest_returns[player]);
+        }
+
+        if let [Some(a), Some(b)] = &record.players[..] {
+            if record.teams[0] != record.teams[1] {
+                self.update_ratings(a, b, record.teams[0] == winner);
+            }
+        }
+    }
+
+    fn update_ratings(&mut self, a: &str, b: &str, a_won: bool) {
+        let rating_a = self.profile_mut(a).rating;
+        let rating_b = self.profile_mut(b).rating;
+        let expected_a = 1.0 / (1.0 + 10f32.powf((rating_b - rating_a) / 400.0));
+        let actual_a = if a_won { 1.0 } else { 0.0 };
+        let change = RATING_K_FACTOR * (actual_a - expected_a);
+        self.profile_mut(a).rating += change;
+        self.profile_mut(b).rating -= change;
+    }
+
+    // Best rated first.
+    pub fn ranked(&self) -> Vec<&Profile> {
+        let mut profiles: Vec<&Profile> = self.profiles.iter().collect();
+        profiles.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        profiles
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "name,rating,wins,losses,longest_rally,fastest_return\n".to_string();
+        for profile in self.ranked() {
+            csv += &format!(
+                "{},{:.0},{},{},{},{:.2}\n",
+                csv_field(&profile.name),
+                profile.rating,
+                profile.wins,
+                profile.losses,
+                profile.longest_rally,
+                profile.fastest_return
+            );
+        }
+        csv
+    }
+}
+
+// The match history is kept as a record per line, oldest first.
+pub fn append_history(record: &MatchRecord) {
+    if let Err(error) = storage::append(HISTORY_NAME, record) {
+        log::error!("could not save match history: {}", error);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+// Names with commas or quotes are quoted, with quotes doubled.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(players: &[Option<&str>], teams: &[usize], winner: Option<usize>) -> MatchRecord {
+        MatchRecord {
+            timestamp: 0,
+            court: Court::Versus,
+            players: players
+                .iter()
+                .map(|name| name.map(str::to_string))
+                .collect(),
+            teams: teams.to_vec(),
+            winner,
+            score: vec![5, 3],
+            longest_rally: 7,
+            fastest_returns: vec![1.5; players.len()],
+        }
+    }
+
+    fn profile<'a>(profiles: &'a Profiles, name: &str) -> &'a Profile {
+        profiles
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn even_players_trade_half_the_k_factor() {
+        let mut profiles = Profiles::default();
+        profiles.record(&record(&[Some("Ann"), Some("Bob")], &[0, 1], Some(0)));
+        let (ann, bob) = (profile(&profiles, "Ann"), profile(&profiles, "Bob"));
+        assert_eq!(ann.rating, STARTING_RATING + RATING_K_FACTOR / 2.0);
+        assert_eq!(bob.rating, STARTING_RATING - RATING_K_FACTOR / 2.0);
+        assert_eq!((ann.wins, ann.losses, bob.wins, bob.losses), (1, 0, 0, 1));
+        assert_eq!(ann.longest_rally, 7);
+        assert_eq!(bob.fastest_return, 1.5);
+    }
+
+    #[test]
+    fn upsets_move_ratings_further_than_expected_wins() {
+        let mut profiles = Profiles::default();
+        profiles.profile_mut("Ann").rating = 1700.0;
+        profiles.profile_mut("Bob").rating = 1300.0;
+        profiles.record(&record(&[Some("Ann"), Some("Bob")], &[0, 1], Some(0)));
+        let expected_win = profile(&profiles, "Ann").rating - 1700.0;
+        profiles.record(&record(&[Some("Ann"), Some("Bob")], &[0, 1], Some(1)));
+        let upset = profile(&profiles, "Bob").rating - (1300.0 - expected_win);
+        assert!(expected_win > 0.0 && expected_win < RATING_K_FACTOR / 2.0);
+        assert!(upset > RATING_K_FACTOR / 2.0);
+        let total: f32 = profiles.profiles.iter().map(|profile| profile.rating).sum();
+        assert!((total - 3000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn ratings_only_change_between_two_profiles_on_opposite_teams() {
+        let mut profiles = Profiles::default();
+        profiles.record(&record(&[Some("Ann"), None], &[0, 1], Some(0)));
+        profiles.record(&record(
+            &[Some("Ann"), Some("Bob"), Some("Cy"), Some("Dee")],
+            &[0, 1, 0, 1],
+            Some(1),
+        ));
+        // Draws count for nobody.
+        profiles.record(&record(&[Some("Ann"), Some("Bob")], &[0, 1], None));
+        for profile in &profiles.profiles {
+            assert_eq!(profile.rating, STARTING_RATING);
+        }
+        assert_eq!(profile(&profiles, "Ann").wins, 1);
+        assert_eq!(profile(&profiles, "Ann").losses, 1);
+        assert_eq!(profile(&profiles, "Dee").wins, 1);
+    }
+
+    #[test]
+    fn csv_quotes_names_that_need_it() {
+        let mut profiles = Profiles::default();
+        profiles.profile_mut("Plain");
+        profiles.profile_mut("Smith, \"Jo\"");
+        let csv = profiles.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[1], "Plain,1500,0,0,0,0.00");
+        assert_eq!(lines[2], "\"Smith, \"\"Jo\"\"\",1500,0,0,0,0.00");
+    }
+}
@@ -0,0 +1,538 @@
+use crate::cli::Options;
+use crate::scene::{self, Shade};
+use crate::settings::Theme;
+use crate::simulation::{Side, Simulation};
+use crate::text::{Text, TextRenderer};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+}
+
+// Everything is drawn as a rectangle: a unit square moved, scaled and coloured
+// per instance, all in clip space.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RectangleRaw {
+    position: [f32; 2],
+    half_size: [f32; 2],
+    color: [f32; 4],
+}
+
+impl RectangleRaw {
+    fn new(position: [f32; 2], half_size: [f32; 2], color: [f32; 3]) -> Self {
+        RectangleRaw {
+            position,
+            half_size,
+            color: [color[0], color[1], color[2], 1.0],
+        }
+    }
+}
+
+const RECTANGLE_INDICES: &[u16] = &[0, 1, 2, 3, 2, 1];
+
+struct WgpuState {
+    backend: wgpu::Backend,
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    swap_chain_descriptor: wgpu::SwapChainDescriptor,
+    swap_chain: wgpu::SwapChain,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+const SCORE_PIP_SIZE: f32 = 12.0;
+const SCORE_PIP_SPACING: f32 = 20.0;
+const SCORE_MARGIN: f32 = 24.0;
+
+// The window's drawable size and DPI scale. Overlays are laid out in logical
+// pixels so they stay the same size on screen regardless of the monitor.
+#[derive(Copy, Clone, Debug)]
+pub struct Viewport {
+    pub size: winit::dpi::PhysicalSize<u32>,
+    pub scale_factor: f64,
+}
+
+impl Viewport {
+    pub fn logical_size(&self) -> winit::dpi::LogicalSize<f32> {
+        let size = winit::dpi::PhysicalSize::new(self.size.width.max(1), self.size.height.max(1));
+        size.to_logical(self.scale_factor)
+    }
+
+    // Converts a point in logical pixels, measured from the top left corner, to
+    // clip space.
+    pub fn point_to_clip(&self, x: f32, y: f32) -> [f32; 2] {
+        let logical_size = self.logical_size();
+        [
+            x / logical_size.width * 2.0 - 1.0,
+            1.0 - y / logical_size.height * 2.0,
+        ]
+    }
+
+    pub fn extent_to_clip(&self, width: f32, height: f32) -> [f32; 2] {
+        let logical_size = self.logical_size();
+        [
+            width / logical_size.width * 2.0,
+            height / logical_size.height * 2.0,
+        ]
+    }
+}
+
+pub enum RenderError {
+    DeviceLost,
+}
+
+pub struct Renderer {
+    wgpu_state: WgpuState,
+    rectangle_vertex_buffer: wgpu::Buffer,
+    rectangle_index_buffer: wgpu::Buffer,
+    court_instance_buffer: wgpu::Buffer,
+    court_count: u32,
+    score_instance_buffer: wgpu::Buffer,
+    score_count: u32,
+    text_renderer: TextRenderer,
+    theme: Theme,
+    viewport: Viewport,
+}
+
+impl Renderer {
+    pub async fn new(
+        window: &winit::window::Window,
+        options: &Options,
+        theme: Theme,
+    ) -> Result<Self, String> {
+        let size = window.inner_size();
+        let viewport = Viewport {
+            size,
+            scale_factor: window.scale_factor(),
+        };
+        let (surface, adapter) = request_adapter(window, options).await?;
+        let adapter_info = adapter.get_info();
+        log::info!(
+            "using {} ({:?}, {:?})",
+            adapter_info.name,
+            adapter_info.backend,
+            adapter_info.device_type
+        );
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Device"),
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .map_err(|error| format!("could not open {}: {}", adapter_info.name, error))?;
+
+        let backend = adapter_info.backend;
+        let swap_chain_descriptor = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: adapter.get_swap_chain_preferred_format(&surface).unwrap(),
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: supported_present_mode(options.present_mode, backend),
+        };
+        let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
+
+        let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+            flags: wgpu::ShaderFlags::all(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_descriptor = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as u64,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x3,
+                offset: 0,
+                shader_location: 0,
+            }],
+        };
+
+        let rectangle_vertex_buffer = Renderer::rectangle_vertex_buffer(&device);
+
+        let rectangle_index_buffer_raw = bytemuck::cast_slice(RECTANGLE_INDICES);
+        let rectangle_index_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            &device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: rectangle_index_buffer_raw,
+                usage: wgpu::BufferUsage::INDEX,
+            },
+        );
+
+        let instance_descriptor = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<RectangleRaw>() as u64,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 2]>() as u64,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 4]>() as u64,
+                    shader_location: 3,
+                },
+            ],
+        };
+
+        let court_instance_buffer =
+            Renderer::instance_buffer(&device, "Court Instance Buffer", &[]);
+        let score_instance_buffer =
+            Renderer::instance_buffer(&device, "Score Instance Buffer", &[]);
+
+        let text_renderer = TextRenderer::new(&device, &queue, swap_chain_descriptor.format);
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "main",
+                buffers: &[vertex_descriptor, instance_descriptor],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: swap_chain_descriptor.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        Ok(Renderer {
+            wgpu_state: WgpuState {
+                backend,
+                surface,
+                device,
+                queue,
+                swap_chain_descriptor,
+                swap_chain,
+                render_pipeline,
+            },
+            rectangle_vertex_buffer,
+            rectangle_index_buffer,
+            court_instance_buffer,
+            court_count: 0,
+            score_instance_buffer,
+            score_count: 0,
+            text_renderer,
+            theme,
+            viewport,
+        })
+    }
+
+    fn rectangle_vertex_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        let vertices = [[1.0, -1.0], [1.0, 1.0], [-1.0, -1.0], [-1.0, 1.0]].map(|[x, y]| Vertex {
+            position: [x, y, 0.0],
+        });
+        wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            },
+        )
+    }
+
+    fn instance_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        instances: &[RectangleRaw],
+    ) -> wgpu::Buffer {
+        wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(instances),
+                usage: wgpu::BufferUsage::VERTEX,
+            },
+        )
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        let wgpu_state = &mut self.wgpu_state;
+        wgpu_state.swap_chain_descriptor.width = size.width;
+        wgpu_state.swap_chain_descriptor.height = size.height;
+        self.recreate_swap_chain();
+        self.viewport.size = size;
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.viewport.scale_factor = scale_factor;
+    }
+
+    fn recreate_swap_chain(&mut self) {
+        let wgpu_state = &mut self.wgpu_state;
+        wgpu_state.swap_chain = wgpu_state
+            .device
+            .create_swap_chain(&wgpu_state.surface, &wgpu_state.swap_chain_descriptor);
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        let present_mode = supported_present_mode(present_mode, self.wgpu_state.backend);
+        if present_mode != self.wgpu_state.swap_chain_descriptor.present_mode {
+            self.wgpu_state.swap_chain_descriptor.present_mode = present_mode;
+            self.recreate_swap_chain();
+        }
+    }
+
+    pub fn update(&mut self, simulation: &Simulation) {
+        self.set_court_buffer(simulation);
+        self.set_score_buffer(simulation);
+    }
+
+    // The whole court in one draw.
+    fn set_court_buffer(&mut self, simulation: &Simulation) {
+        let instances: Vec<RectangleRaw> = scene::court_shapes(simulation)
+            .iter()
+            .map(|shape| {
+                let color = match shape.shade {
+                    Shade::Foreground => self.theme.foreground,
+                    Shade::Highlight => self.theme.highlight,
+                };
+                RectangleRaw::new(shape.position.into(), shape.half_size.into(), color)
+            })
+            .collect();
+
+        self.court_count = instances.len() as u32;
+        self.court_instance_buffer =
+            Renderer::instance_buffer(&self.wgpu_state.device, "Court Instance Buffer", &instances);
+    }
+
+    // Each team's pips are lined up along the top or bottom edge of the window,
+    // starting from the corner or the middle nearest their side.
+    fn set_score_buffer(&mut self, simulation: &Simulation) {
+        let logical_size = self.viewport.logical_size();
+        let [half_width, half_height] = self
+            .viewport
+            .extent_to_clip(SCORE_PIP_SIZE / 2.0, SCORE_PIP_SIZE / 2.0);
+        let top = SCORE_MARGIN + SCORE_PIP_SIZE / 2.0;
+        let bottom = logical_size.height - top;
+        let left = SCORE_MARGIN + SCORE_PIP_SIZE / 2.0;
+        let right = logical_size.width - left;
+
+        let mut instances = Vec::new();
+        for (&side, &score) in simulation.sides.iter().zip(&simulation.score) {
+            let row_width = score.saturating_sub(1) as f32 * SCORE_PIP_SPACING;
+            let (first_x, step, y) = match side {
+                Side::Left => (left, SCORE_PIP_SPACING, top),
+                Side::Right => (right, -SCORE_PIP_SPACING, top),
+                Side::Top => (
+                    (logical_size.width - row_width) / 2.0,
+                    SCORE_PIP_SPACING,
+                    top,
+                ),
+                Side::Bottom => (
+                    (logical_size.width - row_width) / 2.0,
+                    SCORE_PIP_SPACING,
+                    bottom,
+                ),
+            };
+            for i in 0..score {
+                instances.push(RectangleRaw::new(
+                    self.viewport.point_to_clip(first_x + i as f32 * step, y),
+                    [half_width, half_height],
+                    self.theme.foreground,
+                ));
+            }
+        }
+
+        self.score_count = instances.len() as u32;
+        self.score_instance_buffer =
+            Renderer::instance_buffer(&self.wgpu_state.device, "Score Instance Buffer", &instances);
+    }
+
+    pub fn set_text(&mut self, hud: &[Text], menu: &[Text]) {
+        self.text_renderer.prepare(
+            &self.wgpu_state.device,
+            self.viewport,
+            &self.theme,
+            hud,
+            menu,
+        );
+    }
+
+    // wgpu doesn't report device loss directly; running out of memory while
+    // acquiring a frame is the point where the device can't be used any more.
+    pub fn render(&mut self) -> Result<(), RenderError> {
+        let frame = match self.wgpu_state.swap_chain.get_current_frame() {
+            Ok(frame) => frame,
+            Err(wgpu::SwapChainError::Timeout) => return Ok(()),
+            Err(wgpu::SwapChainError::Outdated) | Err(wgpu::SwapChainError::Lost) => {
+                self.recreate_swap_chain();
+                return Ok(());
+            }
+            Err(wgpu::SwapChainError::OutOfMemory) => return Err(RenderError::DeviceLost),
+        };
+        let wgpu_state = &self.wgpu_state;
+        let background = self.theme.background;
+
+        let mut command_encoder =
+            wgpu_state
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Command Encoder"),
+                });
+
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &frame.output.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: background[0] as f64,
+                            g: background[1] as f64,
+                            b: background[2] as f64,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&wgpu_state.render_pipeline);
+            render_pass.set_vertex_buffer(0, self.rectangle_vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                self.rectangle_index_buffer.slice(..),
+                wgpu::IndexFormat::Uint16,
+            );
+            render_pass.set_vertex_buffer(1, self.court_instance_buffer.slice(..));
+            render_pass.draw_indexed(0..RECTANGLE_INDICES.len() as u32, 0, 0..self.court_count);
+
+            render_pass.set_vertex_buffer(1, self.score_instance_buffer.slice(..));
+            render_pass.draw_indexed(0..RECTANGLE_INDICES.len() as u32, 0, 0..self.score_count);
+
+            self.text_renderer.draw(&mut render_pass);
+        }
+
+        wgpu_state
+            .queue
+            .submit(std::iter::once(command_encoder.finish()));
+        Ok(())
+    }
+}
+
+// Tries the requested backends first, then every backend (which includes GL and
+// software rasterizers) with either power preference, before giving up.
+pub async fn request_adapter(
+    window: &winit::window::Window,
+    options: &Options,
+) -> Result<(wgpu::Surface, wgpu::Adapter), String> {
+    let other_power_preference = match options.power_preference {
+        wgpu::PowerPreference::LowPower => wgpu::PowerPreference::HighPerformance,
+        wgpu::PowerPreference::HighPerformance => wgpu::PowerPreference::LowPower,
+    };
+    let attempts = [
+        (options.backend, options.power_preference),
+        (wgpu::BackendBit::all(), options.power_preference),
+        (wgpu::BackendBit::all(), other_power_preference),
+    ];
+
+    for (backend, power_preference) in attempts {
+        let instance = wgpu::Instance::new(backend);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: Some(&surface),
+            })
+            .await;
+        match adapter {
+            Some(adapter) => return Ok((surface, adapter)),
+            None => log::warn!("no {:?} adapter found for {:?}", power_preference, backend),
+        }
+    }
+
+    Err(format!(
+        "no graphics adapter found for {:?}; make sure your graphics drivers are installed, \
+         or run with --list-adapters to see what is available",
+        options.backend
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters() {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+    let mut adapters = instance
+        .enumerate_adapters(wgpu::BackendBit::all())
+        .peekable();
+    if adapters.peek().is_none() {
+        println!("no graphics adapters found");
+    }
+    for adapter in adapters {
+        let info = adapter.get_info();
+        println!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    }
+}
+
+// wgpu can't report which present modes a surface supports yet, so this follows
+// what each backend is known to provide. Fifo is available everywhere, and any
+// other mode a Vulkan or DirectX driver turns down also ends up as Fifo in wgpu.
+fn supported_present_mode(
+    present_mode: wgpu::PresentMode,
+    backend: wgpu::Backend,
+) -> wgpu::PresentMode {
+    let supported = match backend {
+        wgpu::Backend::Vulkan | wgpu::Backend::Dx12 | wgpu::Backend::Dx11 => true,
+        wgpu::Backend::Metal => present_mode != wgpu::PresentMode::Mailbox,
+        _ => present_mode == wgpu::PresentMode::Fifo,
+    };
+
+    if supported {
+        present_mode
+    } else {
+        log::warn!(
+            "{:?} presentation is not supported on {:?}, falling back to Fifo",
+            present_mode,
+            backend
+        );
+        wgpu::PresentMode::Fifo
+    }
+}
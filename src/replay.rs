@@ -0,0 +1,440 @@
+use crate::level::{Level, LevelError};
+use crate::power_up::PowerUpRules;
+use crate::simulation::{
+    BallRules, BallSpeedGrowth, Court, InputFrame, MatchRules, MultiballRules, PaddleRules,
+    Simulation, TICKS_PER_SECOND,
+};
+
+const REPLAY_MAGIC: &[u8; 8] = b"PONGRPLY";
+const REPLAY_VERSION: u16 = 11;
+// A day of play. Anything longer can only be a corrupt file, and would take far
+// too much memory to expand.
+const MAX_REPLAY_TICKS: usize = 24 * 60 * 60 * TICKS_PER_SECOND as usize;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    TooLong,
+    UnknownSpeedGrowth(u8),
+    UnknownCourt(u8),
+    BadRules(serde_json::Error),
+    BadLevel(LevelError),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "could not read replay: {}", error),
+            ReplayError::BadMagic => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {}", version)
+            }
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::TooLong => write!(f, "replay is longer than a day"),
+            ReplayError::UnknownSpeedGrowth(kind) => {
+                write!(f, "unknown ball speed growth {} in replay", kind)
+            }
+            ReplayError::UnknownCourt(court) => write!(f, "unknown court {} in replay", court),
+            ReplayError::BadRules(error) => write!(f, "could not read replay rules: {}", error),
+            ReplayError::BadLevel(error) => write!(f, "replay has a bad level: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+// Header: magic, version, build version, seed and rules. The body is a list of
+// run-length encoded input frames, since paddle input is held for many ticks.
+// Each input frame has a byte per player. Power-up rules and the level are
+// stored as length-prefixed JSON since they are open-ended. Replays from any
+// other version would not play back the same, so they are rejected.
+pub struct Replay {
+    pub build_version: String,
+    pub seed: u64,
+    pub rules: MatchRules,
+    pub frames: Vec<InputFrame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, rules: MatchRules) -> Self {
+        Replay {
+            build_version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            rules,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(REPLAY_MAGIC);
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        let build_version = &self.build_version.as_bytes()[..self.build_version.len().min(255)];
+        bytes.push(build_version.len() as u8);
+        bytes.extend_from_slice(build_version);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.rules.win_score.to_le_bytes());
+        let paddle = self.rules.paddle;
+        for value in [paddle.max_speed, paddle.acceleration, paddle.deceleration] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let (growth_kind, growth_amount) = match self.rules.ball.growth {
+            BallSpeedGrowth::Fixed => (0, 0.0),
+            BallSpeedGrowth::PerHit(increment) => (1, increment),
+            BallSpeedGrowth::Ramp(per_second) => (2, per_second),
+        };
+        bytes.push(growth_kind);
+        bytes.extend_from_slice(&growth_amount.to_le_bytes());
+        bytes.extend_from_slice(&self.rules.ball.max_speed.to_le_bytes());
+        bytes.push(match self.rules.court {
+            Court::Versus => 0,
+            Court::FourPlayer => 1,
+            Court::Doubles => 2,
+            Court::Breakout => 3,
+            Court::Survival => 4,
+            Court::TimeAttack => 5,
+        });
+        bytes.extend_from_slice(&self.rules.lives.to_le_bytes());
+        bytes.extend_from_slice(&self.rules.time_limit.to_le_bytes());
+        let multiball = self.rules.multiball;
+        bytes.extend_from_slice(&multiball.spawn_interval.to_le_bytes());
+        bytes.extend_from_slice(&multiball.max_balls.to_le_bytes());
+        write_json(&mut bytes, &self.rules.power_ups);
+        write_json(&mut bytes, &self.rules.level);
+
+        let mut runs: Vec<(InputFrame, u16)> = Vec::new();
+        for &frame in &self.frames {
+            match runs.last_mut() {
+                Some((run_frame, run_length)) if *run_frame == frame && *run_length < u16::MAX => {
+                    *run_length += 1
+                }
+                _ => runs.push((frame, 1)),
+            }
+        }
+
+        let player_count = self.rules.court.player_count();
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (frame, length) in runs {
+            bytes.extend_from_slice(&frame.to_bytes()[..player_count]);
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ByteReader { bytes, position: 0 };
+
+        if reader.take(REPLAY_MAGIC.len())? != REPLAY_MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let build_version_length = reader.take(1)?[0] as usize;
+        let build_version =
+            String::from_utf8_lossy(reader.take(build_version_length)?).into_owned();
+        let seed = u64::from_le_bytes(reader.array()?);
+        let win_score = u32::from_le_bytes(reader.array()?);
+        let paddle = PaddleRules {
+            max_speed: f32::from_le_bytes(reader.array()?),
+            acceleration: f32::from_le_bytes(reader.array()?),
+            deceleration: f32::from_le_bytes(reader.array()?),
+        };
+        let growth_kind = reader.take(1)?[0];
+        let growth_amount = f32::from_le_bytes(reader.array()?);
+        let ball = BallRules {
+            growth: match growth_kind {
+                0 => BallSpeedGrowth::Fixed,
+                1 => BallSpeedGrowth::PerHit(growth_amount),
+                2 => BallSpeedGrowth::Ramp(growth_amount),
+                kind => return Err(ReplayError::UnknownSpeedGrowth(kind)),
+            },
+            max_speed: f32::from_le_bytes(reader.array()?),
+        };
+        let court = match reader.take(1)?[0] {
+            0 => Court::Versus,
+            1 => Court::FourPlayer,
+            2 => Court::Doubles,
+            3 => Court::Breakout,
+            4 => Court::Survival,
+            5 => Court::TimeAttack,
+            court => return Err(ReplayError::UnknownCourt(court)),
+        };
+        let lives = u32::from_le_bytes(reader.array()?);
+        let time_limit = f32::from_le_bytes(reader.array()?);
+        let multiball = MultiballRules {
+            spawn_interval: f32::from_le_bytes(reader.array()?),
+            max_balls: u32::from_le_bytes(reader.array()?),
+        };
+        let power_ups: PowerUpRules = reader.json()?;
+        let level: Level = reader.json()?;
+        level.validate().map_err(ReplayError::BadLevel)?;
+        let rules = MatchRules {
+            court,
+            win_score,
+            lives,
+            time_limit,
+            paddle,
+            ball,
+            multiball,
+            power_ups,
+            level,
+        };
+
+        let run_count = u32::from_le_bytes(reader.array()?);
+        let mut frames = Vec::new();
+        for _ in 0..run_count {
+            let frame = InputFrame::from_bytes(reader.take(court.player_count())?);
+            let length = u16::from_le_bytes(reader.array()?) as usize;
+            if frames.len() + length > MAX_REPLAY_TICKS {
+                return Err(ReplayError::TooLong);
+            }
+            frames.resize(frames.len() + length, frame);
+        }
+
+        Ok(Replay {
+            build_version,
+            seed,
+            rules,
+            frames,
+        })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, ReplayError> {
+        Replay::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), ReplayError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn simulation(&self) -> Simulation {
+        Simulation::new(self.seed, self.rules.clone())
+    }
+}
+
+fn write_json<T: serde::Serialize>(bytes: &mut Vec<u8>, value: &T) {
+    let json = serde_json::to_vec(value).expect("rules serialize");
+    bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&json);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ReplayError> {
+        let end = self.position + length;
+        let slice = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(ReplayError::Truncated)?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, ReplayError> {
+        let length = u32::from_le_bytes(self.array()?) as usize;
+        serde_json::from_slice(self.take(length)?).map_err(ReplayError::BadRules)
+    }
+}
+
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub paused: bool,
+    pub speed: f32,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        if replay.build_version != env!("CARGO_PKG_VERSION") {
+            log::warn!(
+                "replay was recorded with version {}, playback may diverge",
+                replay.build_version
+            );
+        }
+        ReplayPlayback {
+            replay,
+            paused: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn next_frame(&self, simulation: &Simulation) -> Option<InputFrame> {
+        self.replay.frames.get(simulation.tick as usize).copied()
+    }
+
+    pub fn seek(&self, simulation: &mut Simulation, tick: u64) {
+        let tick = tick.min(self.replay.frames.len() as u64);
+        if tick < simulation.tick {
+            *simulation = self.replay.simulation();
+        }
+        while simulation.tick < tick {
+            let frame = self.replay.frames[simulation.tick as usize];
+            simulation.step(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Difficulty;
+
+    fn rules() -> MatchRules {
+        let mut rules = MatchRules {
+            court: Court::FourPlayer,
+            ball: BallRules {
+                growth: BallSpeedGrowth::Ramp(0.05),
+                ..BallRules::default()
+            },
+            ..MatchRules::default()
+        };
+        rules.power_ups.spawn_interval = 2.0;
+        rules.power_ups.power_ups.truncate(3);
+        rules.level = Level::from_json(
+            br#"{
+                "name": "Pillar",
+                "obstacles": [{
+                    "kind": "block",
+                    "position": [0.0, 0.5],
+                    "half_size": [0.05, 0.1],
+                    "motion": { "offset": [0.3, 0.0], "period": 4.0 }
+                }]
+            }"#,
+        )
+        .unwrap();
+        rules
+    }
+
+    // Records the computer playing every paddle.
+    fn record(ticks: u64) -> (Replay, Simulation) {
+        let mut simulation = Simulation::new(11, rules());
+        let mut replay = Replay::new(simulation.seed, simulation.rules.clone());
+        while simulation.tick < ticks {
+            let mut frame = InputFrame::default();
+            for player in 0..simulation.paddles.len() {
+                frame.paddles[player] = simulation.ai_input(player, Difficulty::Hard);
+            }
+            replay.frames.push(frame);
+            if simulation.step(frame).is_some() {
+                break;
+            }
+        }
+        (replay, simulation)
+    }
+
+    fn state(simulation: &Simulation) -> String {
+        format!(
+            "{} {:?} {:?} {:?}",
+            simulation.tick, simulation.score, simulation.paddles, simulation.balls
+        )
+    }
+
+    #[test]
+    fn replays_round_trip_and_play_back_the_same_match() {
+        let (replay, recorded) = record(1500);
+        let decoded = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        assert_eq!(decoded.build_version, replay.build_version);
+        assert_eq!(decoded.seed, replay.seed);
+        assert_eq!(decoded.rules, replay.rules);
+        assert!(decoded.frames == replay.frames);
+
+        let playback = ReplayPlayback::new(decoded);
+        let mut simulation = playback.replay.simulation();
+        playback.seek(&mut simulation, u64::MAX);
+        assert_eq!(state(&simulation), state(&recorded));
+        // Seeking back starts over from the beginning.
+        playback.seek(&mut simulation, 10);
+        assert_eq!(simulation.tick, 10);
+    }
+
+    #[test]
+    fn other_files_and_versions_are_rejected() {
+        let bytes = record(10).0.to_bytes();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            Replay::from_bytes(&bad_magic),
+            Err(ReplayError::BadMagic)
+        ));
+
+        let mut old_version = bytes.clone();
+        old_version[8..10].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&old_version),
+            Err(ReplayError::UnsupportedVersion(1))
+        ));
+
+        for length in [0, 9, bytes.len() / 2, bytes.len() - 1] {
+            assert!(matches!(
+                Replay::from_bytes(&bytes[..length]),
+                Err(ReplayError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_rules_are_rejected() {
+        let replay = Replay::new(1, MatchRules::default());
+        let bytes = replay.to_bytes();
+        // The speed growth kind comes after the magic, version, build version,
+        // seed, win score and paddle rules, then its amount and the top speed.
+        let growth = 8 + 2 + 1 + replay.build_version.len() + 8 + 4 + 3 * 4;
+        let court = growth + 1 + 4 + 4;
+
+        let mut bad_growth = bytes.clone();
+        bad_growth[growth] = 9;
+        assert!(matches!(
+            Replay::from_bytes(&bad_growth),
+            Err(ReplayError::UnknownSpeedGrowth(9))
+        ));
+
+        let mut bad_court = bytes;
+        bad_court[court] = 9;
+        assert!(matches!(
+            Replay::from_bytes(&bad_court),
+            Err(ReplayError::UnknownCourt(9))
+        ));
+    }
+
+    #[test]
+    fn run_lengths_past_a_day_are_rejected() {
+        let mut bytes = Replay::new(1, MatchRules::default()).to_bytes();
+        let run_count = bytes.len() - 4;
+        let runs = MAX_REPLAY_TICKS / u16::MAX as usize + 1;
+        bytes.truncate(run_count);
+        bytes.extend_from_slice(&(runs as u32).to_le_bytes());
+        for _ in 0..runs {
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(&u16::MAX.to_le_bytes());
+        }
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::TooLong)
+        ));
+    }
+}
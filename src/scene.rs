@@ -0,0 +1,94 @@
+use crate::level::ObstacleKind;
+use crate::simulation::{Side, Simulation, BALL_HALF_SIZE, PICKUP_HALF_SIZE};
+use cgmath::Vector2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shade {
+    Foreground,
+    Highlight,
+}
+
+// In view space, where the court is shrunk to fit -1 to 1 both ways.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Shape {
+    pub position: Vector2<f32>,
+    pub half_size: Vector2<f32>,
+    pub shade: Shade,
+}
+
+// Everything drawn on the court, back to front: paddles still in the match,
+// walls for the sides of teams who are out or shielded and for open sides short
+// of the window, the level's obstacles, bricks, pickups and every visible ball.
+// Power-ups, bumpers, portals, shields and bricks that take more than one hit
+// are highlighted.
+pub fn court_shapes(simulation: &Simulation) -> Vec<Shape> {
+    use Shade::{Foreground, Highlight};
+
+    let level = &simulation.rules.level;
+    let extent = level.extent();
+    let scale = level.view_scale();
+    let paddles = simulation
+        .paddles
+        .iter()
+        .filter(|paddle| !simulation.is_eliminated(paddle.team))
+        .map(|paddle| (paddle.position, paddle.half_size(), Foreground));
+    let team_walls = (0..simulation.sides.len()).filter_map(|team| {
+        let shade = if simulation.is_eliminated(team) {
+            Foreground
+        } else if simulation.is_shielded(team) {
+            Highlight
+        } else {
+            return None;
+        };
+        let (position, half_size) = simulation.sides[team].wall(extent);
+        Some((position, half_size, shade))
+    });
+    let open_walls = Side::ALL
+        .iter()
+        .filter(|side| !simulation.sides.contains(side) && side.depth(extent) * scale < 1.0)
+        .map(|side| {
+            let (position, half_size) = side.wall(extent);
+            (position, half_size, Foreground)
+        });
+    let obstacles = level.obstacles.iter().enumerate().map(|(index, obstacle)| {
+        let shade = match obstacle.kind {
+            ObstacleKind::Block => Foreground,
+            ObstacleKind::Bumper(_) | ObstacleKind::Portal(_) => Highlight,
+        };
+        let position = simulation.obstacle_position(index);
+        (position, obstacle.half_size.into(), shade)
+    });
+    let bricks = simulation.bricks.iter().map(|brick| {
+        let shade = if brick.hits_left > 1 {
+            Highlight
+        } else {
+            Foreground
+        };
+        (brick.position, brick.half_size, shade)
+    });
+    let pickups = simulation.pickups.iter().map(|pickup| {
+        let half_size = Vector2::new(PICKUP_HALF_SIZE, PICKUP_HALF_SIZE);
+        (pickup.position, half_size, Highlight)
+    });
+    let balls = simulation
+        .balls
+        .iter()
+        .filter(|ball| simulation.is_visible(ball))
+        .map(|ball| {
+            let half_size = Vector2::new(BALL_HALF_SIZE, BALL_HALF_SIZE);
+            (ball.position, half_size, Foreground)
+        });
+    paddles
+        .chain(team_walls)
+        .chain(open_walls)
+        .chain(obstacles)
+        .chain(bricks)
+        .chain(pickups)
+        .chain(balls)
+        .map(|(position, half_size, shade)| Shape {
+            position: position * scale,
+            half_size: half_size * scale,
+            shade,
+        })
+        .collect()
+}
@@ -0,0 +1,308 @@
+use crate::simulation::{Difficulty, MatchRules};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "pong-wgpu-settings";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerPreference {
+    Low,
+    High,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::Low => wgpu::PowerPreference::LowPower,
+            PowerPreference::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+impl WindowMode {
+    pub fn next(self) -> Self {
+        match self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Exclusive,
+            WindowMode::Exclusive => WindowMode::Windowed,
+        }
+    }
+}
+
+// A fullscreen resolution. Without a refresh rate the highest one available is used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub width: u32,
+    pub height: u32,
+    pub window_mode: WindowMode,
+    pub monitor: Option<usize>,
+    pub video_mode: Option<VideoMode>,
+    pub present_mode: PresentMode,
+    #[serde(deserialize_with = "frame_rate_limit")]
+    pub frame_rate_limit: Option<u32>,
+    pub power_preference: PowerPreference,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        VideoSettings {
+            width: 800,
+            height: 600,
+            window_mode: WindowMode::Windowed,
+            monitor: None,
+            video_mode: None,
+            present_mode: PresentMode::Mailbox,
+            frame_rate_limit: None,
+            power_preference: PowerPreference::Low,
+        }
+    }
+}
+
+// A limit of 0 means no limit, as on the command line.
+fn frame_rate_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Ok(Option::<u32>::deserialize(deserializer)?.filter(|&frame_rate_limit| frame_rate_limit > 0))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub background: [f32; 3],
+    pub foreground: [f32; 3],
+    pub highlight: [f32; 3],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: [0.0, 0.0, 0.0],
+            foreground: [0.9, 0.9, 0.9],
+            highlight: [1.0, 0.8, 0.2],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub theme: Theme,
+    pub difficulty: Difficulty,
+    pub rules: MatchRules,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            video: VideoSettings::default(),
+            theme: Theme::default(),
+            difficulty: Difficulty::Normal,
+            rules: MatchRules::default(),
+        }
+    }
+}
+
+impl Settings {
+    // Missing or unreadable settings fall back to the defaults, so a broken file
+    // never keeps the game from starting.
+    pub fn load() -> Self {
+        match Settings::read() {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                let settings = Settings::default();
+                settings.save();
+                settings
+            }
+            Err(error) => {
+                log::error!("could not load settings: {}", error);
+                Settings::default()
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read() -> Result<Option<Self>, String> {
+        let path = match settings_path() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Settings::parse(&contents)
+                .map(Some)
+                .map_err(|error| format!("{}: {}", path.display(), error)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn read() -> Result<Option<Self>, String> {
+        match local_storage().and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten()) {
+            Some(contents) => Settings::parse(&contents).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Rules are held to the same limits as on the command line.
+    fn parse(contents: &str) -> Result<Self, String> {
+        let settings: Settings =
+            serde_json::from_str(contents).map_err(|error| error.to_string())?;
+        settings.rules.validate()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self) {
+        let contents = match serde_json::to_string_pretty(self) {
+            Ok(contents) => contents,
+            Err(error) => {
+                log::error!("could not serialize settings: {}", error);
+                return;
+            }
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = settings_path() {
+            let result = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&path, contents));
+            if let Err(error) = result {
+                log::error!("could not save settings to {}: {}", path.display(), error);
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Some(storage) = local_storage() {
+            if storage.set_item(STORAGE_KEY, &contents).is_err() {
+                log::error!("could not save settings to local storage");
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn settings_path() -> Option<std::path::PathBuf> {
+    dirs::config_dir().map(|directory| directory.join("pong-wgpu").join("settings.json"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+// Polls the settings file for edits made while the game is running. There is
+// nothing to watch in the browser, so this never reports changes there.
+pub struct SettingsWatcher {
+    #[cfg(not(target_arch = "wasm32"))]
+    modified: Option<std::time::SystemTime>,
+    last_check: instant::Instant,
+}
+
+impl SettingsWatcher {
+    pub fn new() -> Self {
+        SettingsWatcher {
+            #[cfg(not(target_arch = "wasm32"))]
+            modified: SettingsWatcher::modified(),
+            last_check: instant::Instant::now(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn modified() -> Option<std::time::SystemTime> {
+        std::fs::metadata(settings_path()?).ok()?.modified().ok()
+    }
+
+    pub fn poll(&mut self) -> Option<Settings> {
+        if self.last_check.elapsed() < std::time::Duration::from_secs(1) {
+            return None;
+        }
+        self.last_check = instant::Instant::now();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let modified = SettingsWatcher::modified();
+            if modified != self.modified {
+                self.modified = modified;
+                return match Settings::read() {
+                    Ok(settings) => settings,
+                    Err(error) => {
+                        log::error!("could not reload settings: {}", error);
+                        None
+                    }
+                };
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_rate_limit(json: &str) -> Option<u32> {
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        settings.video.frame_rate_limit
+    }
+
+    #[test]
+    fn zero_frame_rate_limit_means_no_limit() {
+        assert_eq!(
+            frame_rate_limit(r#"{"video": {"frame_rate_limit": 0}}"#),
+            None
+        );
+        assert_eq!(
+            frame_rate_limit(r#"{"video": {"frame_rate_limit": null}}"#),
+            None
+        );
+        assert_eq!(frame_rate_limit(r#"{"video": {}}"#), None);
+        assert_eq!(
+            frame_rate_limit(r#"{"video": {"frame_rate_limit": 30}}"#),
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn rules_are_held_to_the_command_line_limits() {
+        assert!(Settings::parse(r#"{"rules": {"lives": 2, "win_score": 3}}"#).is_ok());
+        assert!(Settings::parse(r#"{"rules": {"lives": 0}}"#).is_err());
+        assert!(Settings::parse(r#"{"rules": {"win_score": 0}}"#).is_err());
+        assert!(Settings::parse(r#"{"rules": {"time_limit": 0.0}}"#).is_err());
+        assert!(Settings::parse(r#"{"rules": {"multiball": {"spawn_interval": -1.0}}}"#).is_err());
+    }
+}
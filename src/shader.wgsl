@@ -1,7 +1,8 @@
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
-    [[location(1)]] color: vec4<f32>;
-    [[location(2)]] transform: vec3<f32>;
+    [[location(1)]] transform: vec2<f32>;
+    [[location(2)]] half_size: vec2<f32>;
+    [[location(3)]] color: vec4<f32>;
 };
 
 struct FragmentInput {
@@ -12,7 +13,8 @@ struct FragmentInput {
 [[stage(vertex)]]
 fn main(in: VertexInput) -> FragmentInput {
     var fragment_input: FragmentInput;
-    fragment_input.clip_position = vec4<f32>(in.position + in.transform, 1.0);
+    let position = in.position.xy * in.half_size + in.transform;
+    fragment_input.clip_position = vec4<f32>(position, in.position.z, 1.0);
     fragment_input.color = in.color;
     return fragment_input;
 };
@@ -0,0 +1,1203 @@
+use crate::level::{Brick, BrickGrid, Level, ObstacleKind};
+use crate::power_up::{ActiveEffect, Effect, Pickup, PowerUpRules, Target};
+use cgmath::{Deg, Vector2};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+pub const TICKS_PER_SECOND: u32 = 60;
+pub const TICK_SECONDS: f32 = 1.0 / TICKS_PER_SECOND as f32;
+
+// Sizes in court units, where the court spans -1 to 1 both ways.
+pub const PADDLE_HALF_WIDTH: f32 = 0.025;
+pub const PADDLE_HALF_HEIGHT: f32 = 0.15;
+pub const BALL_HALF_SIZE: f32 = 0.025;
+pub const PICKUP_HALF_SIZE: f32 = 0.04;
+const BRICK_POINTS: u32 = 10;
+// Drawn along the sides of eliminated teams.
+pub const WALL_HALF_THICKNESS: f32 = 0.01;
+// How far paddles are from the middle of the court, in front of their goal or
+// further up in doubles.
+const BACK_PADDLE_DISTANCE: f32 = 0.9;
+const FORWARD_PADDLE_DISTANCE: f32 = 0.5;
+pub const MAX_PADDLES: usize = 4;
+
+pub fn tick_duration() -> std::time::Duration {
+    std::time::Duration::from_secs(1) / TICKS_PER_SECOND
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchRules {
+    pub court: Court,
+    pub win_score: u32,
+    // Only used on courts where teams have lives instead of scoring points.
+    pub lives: u32,
+    // How long a time attack lasts, in seconds.
+    pub time_limit: f32,
+    pub paddle: PaddleRules,
+    pub ball: BallRules,
+    pub multiball: MultiballRules,
+    pub power_ups: PowerUpRules,
+    #[serde(deserialize_with = "crate::level::valid_level")]
+    pub level: Level,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            court: Court::Versus,
+            win_score: 5,
+            lives: 3,
+            time_limit: 60.0,
+            paddle: PaddleRules::default(),
+            ball: BallRules::default(),
+            multiball: MultiballRules::default(),
+            power_ups: PowerUpRules::default(),
+            level: Level::default(),
+        }
+    }
+}
+
+impl MatchRules {
+    // The checks the command line makes on each rule, for rules from anywhere
+    // else. Levels and power-ups are already checked as they are read.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.win_score == 0 {
+            return Err("win_score must be at least 1".to_string());
+        }
+        if self.lives == 0 {
+            return Err("lives must be at least 1".to_string());
+        }
+        if self.time_limit <= 0.0 {
+            return Err("time_limit must be more than 0".to_string());
+        }
+        if self.multiball.spawn_interval < 0.0 || self.power_ups.spawn_interval < 0.0 {
+            return Err("spawn_interval must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Speeds are in court units per second, acceleration and deceleration in court
+// units per second squared. Deceleration applies when slowing down or turning.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaddleRules {
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+}
+
+impl Default for PaddleRules {
+    fn default() -> Self {
+        PaddleRules {
+            max_speed: 2.0,
+            acceleration: 16.0,
+            deceleration: 24.0,
+        }
+    }
+}
+
+// Ball speeds are multiples of the speed the ball is served at. It never gets
+// faster than `max_speed` and slows back down for every serve.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BallRules {
+    pub growth: BallSpeedGrowth,
+    pub max_speed: f32,
+}
+
+impl Default for BallRules {
+    fn default() -> Self {
+        BallRules {
+            growth: BallSpeedGrowth::PerHit(0.1),
+            max_speed: 2.5,
+        }
+    }
+}
+
+// Extra balls are served from the middle every `spawn_interval` seconds, never
+// more than `max_balls` in play at once. An interval of 0 turns it off.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultiballRules {
+    pub spawn_interval: f32,
+    pub max_balls: u32,
+}
+
+impl Default for MultiballRules {
+    fn default() -> Self {
+        MultiballRules {
+            spawn_interval: 0.0,
+            max_balls: 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BallSpeedGrowth {
+    Fixed,
+    // Added every time a paddle returns the ball.
+    PerHit(f32),
+    // Added every second the ball is in play.
+    Ramp(f32),
+}
+
+// Every team defends the goal on its own side. Versus has a team on the left and
+// right with walls above and below. With four players every side has a team and
+// each team has a number of lives; a team that runs out is out of the match and
+// its side becomes a wall. Doubles is versus with a back and a forward paddle
+// on each side. Breakout is a single player at the bottom with lives, knocking
+// out bricks. Survival is a single player against three walls, out on the first
+// miss, and time attack is versus against the clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Court {
+    Versus,
+    FourPlayer,
+    Doubles,
+    Breakout,
+    Survival,
+    TimeAttack,
+}
+
+impl Court {
+    fn sides(self) -> &'static [Side] {
+        match self {
+            Court::Versus | Court::Doubles | Court::TimeAttack => &[Side::Left, Side::Right],
+            Court::FourPlayer => &[Side::Left, Side::Right, Side::Top, Side::Bottom],
+            Court::Breakout => &[Side::Bottom],
+            Court::Survival => &[Side::Left],
+        }
+    }
+
+    // Where each player's paddle plays: the team it belongs to and how far from
+    // the middle of the court it is.
+    fn roster(self) -> &'static [(usize, f32)] {
+        match self {
+            Court::Versus | Court::TimeAttack => {
+                &[(0, BACK_PADDLE_DISTANCE), (1, BACK_PADDLE_DISTANCE)]
+            }
+            Court::FourPlayer => &[
+                (0, BACK_PADDLE_DISTANCE),
+                (1, BACK_PADDLE_DISTANCE),
+                (2, BACK_PADDLE_DISTANCE),
+                (3, BACK_PADDLE_DISTANCE),
+            ],
+            Court::Doubles => &[
+                (0, BACK_PADDLE_DISTANCE),
+                (1, BACK_PADDLE_DISTANCE),
+                (0, FORWARD_PADDLE_DISTANCE),
+                (1, FORWARD_PADDLE_DISTANCE),
+            ],
+            Court::Breakout | Court::Survival => &[(0, BACK_PADDLE_DISTANCE)],
+        }
+    }
+
+    pub fn player_count(self) -> usize {
+        self.roster().len()
+    }
+
+    pub fn uses_lives(self) -> bool {
+        matches!(self, Court::FourPlayer | Court::Breakout | Court::Survival)
+    }
+
+    // Challenges are played by the first player for points, which go on a high
+    // score table.
+    pub fn is_challenge(self) -> bool {
+        matches!(self, Court::Breakout | Court::Survival | Court::TimeAttack)
+    }
+
+    // The first ball goes towards a paddle rather than a wall.
+    fn first_serve(self) -> Deg<f32> {
+        match self {
+            Court::Breakout => Deg(170.0),
+            Court::Survival => Deg(-80.0),
+            _ => Deg(80.0),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    // Points out of the court through this side.
+    pub fn normal(self) -> Vector2<f32> {
+        match self {
+            Side::Left => Vector2::new(-1.0, 0.0),
+            Side::Right => Vector2::new(1.0, 0.0),
+            Side::Top => Vector2::new(0.0, 1.0),
+            Side::Bottom => Vector2::new(0.0, -1.0),
+        }
+    }
+
+    // Paddles on the top and bottom move left and right.
+    pub fn is_horizontal(self) -> bool {
+        matches!(self, Side::Top | Side::Bottom)
+    }
+
+    // The direction a paddle on this side moves in for positive input.
+    fn axis(self) -> Vector2<f32> {
+        if self.is_horizontal() {
+            Vector2::new(1.0, 0.0)
+        } else {
+            Vector2::new(0.0, 1.0)
+        }
+    }
+
+    fn along(self, point: Vector2<f32>) -> f32 {
+        cgmath::InnerSpace::dot(point, self.axis())
+    }
+
+    // How far this side is from the middle of a court of the given extent.
+    pub fn depth(self, extent: Vector2<f32>) -> f32 {
+        cgmath::InnerSpace::dot(extent, self.normal()).abs()
+    }
+
+    fn half_size(self, half_length: f32, half_thickness: f32) -> Vector2<f32> {
+        if self.is_horizontal() {
+            Vector2::new(half_length, half_thickness)
+        } else {
+            Vector2::new(half_thickness, half_length)
+        }
+    }
+
+    pub fn wall(self, extent: Vector2<f32>) -> (Vector2<f32>, Vector2<f32>) {
+        (
+            self.normal() * (self.depth(extent) - WALL_HALF_THICKNESS),
+            self.half_size(self.along(extent), WALL_HALF_THICKNESS),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    fn dead_zone(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.05,
+            Difficulty::Hard => 0.01,
+        }
+    }
+}
+
+// How fast a paddle should move, from -127 (full speed down) to 127 (full speed
+// up). Analog input is quantized to this so replays stay deterministic.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PaddleInput {
+    pub movement: i8,
+}
+
+impl PaddleInput {
+    const FULL_SPEED: i8 = 127;
+
+    pub fn from_buttons(up: bool, down: bool) -> Self {
+        let movement = match (up, down) {
+            (true, false) => PaddleInput::FULL_SPEED,
+            (false, true) => -PaddleInput::FULL_SPEED,
+            _ => 0,
+        };
+        PaddleInput { movement }
+    }
+
+    pub fn from_axis(value: f32) -> Self {
+        PaddleInput {
+            movement: (value.clamp(-1.0, 1.0) * PaddleInput::FULL_SPEED as f32).round() as i8,
+        }
+    }
+
+    pub fn speed(self) -> f32 {
+        self.movement as f32 / PaddleInput::FULL_SPEED as f32
+    }
+
+    // Whichever input asks for more movement wins.
+    pub fn max(self, other: PaddleInput) -> Self {
+        if other.movement.unsigned_abs() > self.movement.unsigned_abs() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputFrame {
+    pub paddles: [PaddleInput; MAX_PADDLES],
+}
+
+impl InputFrame {
+    pub fn to_bytes(self) -> [u8; MAX_PADDLES] {
+        self.paddles.map(|paddle| paddle.movement as u8)
+    }
+
+    // Paddles that aren't in the bytes get no input.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut frame = InputFrame::default();
+        for (paddle, &byte) in frame.paddles.iter_mut().zip(bytes) {
+            paddle.movement = byte as i8;
+        }
+        frame
+    }
+}
+
+// Scores are listed per team. On courts with lives they are the lives each team
+// had left. A single player who runs out of lives has no winner, and their
+// points are what they scored.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MatchResult {
+    pub winner: Option<usize>,
+    pub score: Vec<u32>,
+    pub points: u32,
+    pub stats: MatchStats,
+}
+
+// Rallies are in paddle hits, one for every ball that went in, and the longest
+// also counts balls still in play. Hits and the fastest ball returned are per
+// player, with speeds a multiple of the serve speed. Possession is the time each
+// team was the last to hit a ball, and serves are counted for the team the ball
+// was served towards.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MatchStats {
+    pub rallies: Vec<u32>,
+    pub longest_rally: u32,
+    pub hits: Vec<u32>,
+    pub fastest_returns: Vec<f32>,
+    pub max_ball_speed: f32,
+    pub possession_seconds: Vec<f32>,
+    pub serves: Vec<ServeStats>,
+}
+
+impl MatchStats {
+    fn new(player_count: usize, team_count: usize) -> Self {
+        MatchStats {
+            hits: vec![0; player_count],
+            fastest_returns: vec![0.0; player_count],
+            possession_seconds: vec![0.0; team_count],
+            serves: vec![ServeStats::default(); team_count],
+            ..MatchStats::default()
+        }
+    }
+
+    pub fn average_rally(&self) -> f32 {
+        if self.rallies.is_empty() {
+            return 0.0;
+        }
+        self.rallies.iter().sum::<u32>() as f32 / self.rallies.len() as f32
+    }
+}
+
+// Of the balls served towards a team that went in, how many went in somewhere
+// other than its own goal.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ServeStats {
+    pub received: u32,
+    pub won: u32,
+}
+
+// The distance from the middle is a share of the way to the paddle's side.
+#[derive(Copy, Clone, Debug)]
+pub struct Paddle {
+    pub team: usize,
+    pub side: Side,
+    distance: f32,
+    pub position: Vector2<f32>,
+    pub velocity: f32,
+    // How much longer than usual power-ups make the paddle.
+    pub scale: f32,
+}
+
+impl Paddle {
+    fn new(team: usize, side: Side, distance: f32, extent: Vector2<f32>) -> Self {
+        Paddle {
+            team,
+            side,
+            distance,
+            position: side.normal() * side.depth(extent) * distance,
+            velocity: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    fn half_length(&self) -> f32 {
+        PADDLE_HALF_HEIGHT * self.scale
+    }
+
+    pub fn half_size(&self) -> Vector2<f32> {
+        self.side.half_size(self.half_length(), PADDLE_HALF_WIDTH)
+    }
+}
+
+// Speed is a multiple of the serve speed, and the direction an angle clockwise
+// from straight up. The team that last hit the ball is the one that collects any
+// power-up it passes through. A ball coming out of a portal remembers it so it
+// isn't sent straight back. Hits counts paddle returns since the ball was
+// served towards the side it was heading for most directly.
+#[derive(Copy, Clone, Debug)]
+pub struct Ball {
+    pub position: Vector2<f32>,
+    pub direction: Deg<f32>,
+    pub speed: f32,
+    pub last_hit: Option<usize>,
+    pub hits: u32,
+    served_to: Side,
+    in_portal: Option<usize>,
+}
+
+impl Ball {
+    fn serve(direction: Deg<f32>) -> Self {
+        let mut ball = Ball {
+            position: Vector2::new(0.0, 0.0),
+            direction,
+            speed: 1.0,
+            last_hit: None,
+            hits: 0,
+            served_to: Side::Left,
+            in_portal: None,
+        };
+        let heading = ball.heading();
+        ball.served_to = Side::ALL
+            .iter()
+            .copied()
+            .max_by(|a, b| {
+                let a = cgmath::InnerSpace::dot(heading, a.normal());
+                let b = cgmath::InnerSpace::dot(heading, b.normal());
+                a.total_cmp(&b)
+            })
+            .unwrap_or(Side::Left);
+        ball
+    }
+
+    pub fn heading(&self) -> Vector2<f32> {
+        Vector2::new(
+            cgmath::Angle::sin(self.direction),
+            cgmath::Angle::cos(self.direction),
+        )
+    }
+
+    // Mirrors the ball off a surface facing along one of the axes, unless it is
+    // already moving away from it.
+    fn reflect(&mut self, surface_normal: Vector2<f32>) -> bool {
+        if cgmath::InnerSpace::dot(self.heading(), surface_normal) >= 0.0 {
+            return false;
+        }
+        if surface_normal.x == 0.0 {
+            self.direction.0 = 180.0 - self.direction.0;
+        } else {
+            self.direction = -self.direction;
+        }
+        true
+    }
+
+    // Turns the ball back into the court if it is heading out through the side.
+    // This also lets a ball that is already past a forward paddle through it.
+    fn bounce_away_from(&mut self, side: Side, rng: &mut rand::rngs::StdRng) -> bool {
+        if !self.reflect(-side.normal()) {
+            return false;
+        }
+        self.direction.0 += (rng.gen::<f32>() - 0.5) * 20.0;
+        true
+    }
+
+    // If the ball overlaps a box, the outward normal of the face it is least far
+    // past, which is the one it came in through, and how far past it it is.
+    fn overlap(
+        &self,
+        position: Vector2<f32>,
+        half_size: Vector2<f32>,
+    ) -> Option<(Vector2<f32>, f32)> {
+        let offset = self.position - position;
+        let overlap = Vector2::new(
+            half_size.x + BALL_HALF_SIZE - offset.x.abs(),
+            half_size.y + BALL_HALF_SIZE - offset.y.abs(),
+        );
+        if overlap.x <= 0.0 || overlap.y <= 0.0 {
+            None
+        } else if overlap.x < overlap.y {
+            Some((Vector2::new(offset.x.signum(), 0.0), overlap.x))
+        } else {
+            Some((Vector2::new(0.0, offset.y.signum()), overlap.y))
+        }
+    }
+
+    fn speed_up(&mut self, amount: f32, max_speed: f32) {
+        self.speed = (self.speed + amount).min(max_speed);
+    }
+}
+
+// Each player has one paddle, on the side of the court their team defends. Team
+// scores count up in points, or down in lives on courts that use them. Points
+// are the first player's score in challenges: a hit on a brick, a return in
+// survival or a goal in time attack.
+pub struct Simulation {
+    pub seed: u64,
+    pub rules: MatchRules,
+    pub tick: u64,
+    pub paddles: Vec<Paddle>,
+    pub sides: Vec<Side>,
+    pub score: Vec<u32>,
+    pub balls: Vec<Ball>,
+    pub pickups: Vec<Pickup>,
+    pub effects: Vec<ActiveEffect>,
+    pub bricks: Vec<Brick>,
+    pub points: u32,
+    pub stats: MatchStats,
+    rng: rand::rngs::StdRng,
+}
+
+impl Simulation {
+    pub fn new(seed: u64, rules: MatchRules) -> Self {
+        let sides = rules.court.sides();
+        let starting_score = match rules.court {
+            Court::Survival => 1,
+            court if court.uses_lives() => rules.lives,
+            _ => 0,
+        };
+        let extent = rules.level.extent();
+        // Breakout needs bricks to break, so it falls back to a standard wall of them.
+        let bricks = match (&rules.level.bricks, rules.court) {
+            (Some(grid), _) => grid.bricks(),
+            (None, Court::Breakout) => BrickGrid::default().bricks(),
+            (None, _) => Vec::new(),
+        };
+        let paddles = rules
+            .court
+            .roster()
+            .iter()
+            .map(|&(team, distance)| Paddle::new(team, sides[team], distance, extent))
+            .collect::<Vec<_>>();
+        Simulation {
+            seed,
+            tick: 0,
+            sides: sides.to_vec(),
+            score: vec![starting_score; sides.len()],
+            balls: vec![Ball::serve(rules.court.first_serve())],
+            pickups: Vec::new(),
+            effects: Vec::new(),
+            bricks,
+            points: 0,
+            stats: MatchStats::new(paddles.len(), sides.len()),
+            paddles,
+            rules,
+            rng: rand::rngs::StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn is_eliminated(&self, team: usize) -> bool {
+        self.rules.court.uses_lives() && self.score[team] == 0
+    }
+
+    // Sides without a team still in the match are walls.
+    fn defender(&self, side: Side) -> Option<usize> {
+        self.sides
+            .iter()
+            .position(|&team_side| team_side == side)
+            .filter(|&team| !self.is_eliminated(team))
+    }
+
+    pub fn step(&mut self, input: InputFrame) -> Option<MatchResult> {
+        self.tick += 1;
+        self.update_effects();
+
+        for player in 0..self.paddles.len() {
+            if !self.is_eliminated(self.paddles[player].team) {
+                self.move_paddle(player, input.paddles[player]);
+            }
+        }
+
+        if self.is_due(self.rules.multiball.spawn_interval) {
+            self.spawn_ball();
+        }
+        if self.is_due(self.rules.power_ups.spawn_interval) {
+            self.spawn_pickup();
+        }
+
+        // Goals scored by different balls on the same tick all count.
+        let mut conceding_teams = Vec::new();
+        let mut collected = Vec::new();
+        let mut serve_direction = Deg(0.0);
+        let mut balls = std::mem::take(&mut self.balls);
+        balls.retain_mut(|ball| match self.move_ball(ball, &mut collected) {
+            Some(team) => {
+                self.count_rally(ball, team);
+                conceding_teams.push(team);
+                serve_direction = ball.direction;
+                false
+            }
+            None => true,
+        });
+        self.balls = balls;
+        for (power_up, team) in collected {
+            self.collect(power_up, team);
+        }
+        if !conceding_teams.is_empty() {
+            self.score_goals(&conceding_teams);
+            // Play only stops once the last ball is gone, and restarts with it
+            // served the way it went out.
+            if self.balls.is_empty() {
+                self.reset_objects(serve_direction);
+            }
+        }
+        self.result()
+    }
+
+    // Breakout is won by breaking every brick and lost by running out of lives.
+    // Survival can only be lost. Time attack ends when the time is up, with
+    // whoever is ahead winning.
+    fn result(&self) -> Option<MatchResult> {
+        let winner = match self.rules.court {
+            Court::Breakout | Court::Survival if self.is_eliminated(0) => None,
+            Court::Breakout if self.bricks.is_empty() => Some(0),
+            Court::Breakout | Court::Survival => return None,
+            Court::TimeAttack => {
+                if self.time_left()? > 0.0 {
+                    return None;
+                }
+                match self.score[0].cmp(&self.score[1]) {
+                    std::cmp::Ordering::Greater => Some(0),
+                    std::cmp::Ordering::Less => Some(1),
+                    std::cmp::Ordering::Equal => None,
+                }
+            }
+            _ => Some(self.winner()?),
+        };
+        Some(self.result_with(winner))
+    }
+
+    // Ends the match where it stands, e.g. when a match between computers is cut
+    // short, with no winner.
+    pub fn draw(&self) -> MatchResult {
+        self.result_with(None)
+    }
+
+    fn result_with(&self, winner: Option<usize>) -> MatchResult {
+        MatchResult {
+            winner,
+            score: self.score.clone(),
+            points: self.points,
+            stats: self.stats.clone(),
+        }
+    }
+
+    // Records a ball going in, before the goal is scored so the team it was
+    // served towards is still in.
+    fn count_rally(&mut self, ball: &Ball, conceding_team: usize) {
+        self.stats.rallies.push(ball.hits);
+        if let Some(team) = self.defender(ball.served_to) {
+            self.stats.serves[team].received += 1;
+            if team != conceding_team {
+                self.stats.serves[team].won += 1;
+            }
+        }
+    }
+
+    // Seconds until a time attack is over.
+    pub fn time_left(&self) -> Option<f32> {
+        if self.rules.court != Court::TimeAttack {
+            return None;
+        }
+        Some((self.rules.time_limit - self.tick as f32 * TICK_SECONDS).max(0.0))
+    }
+
+    // Whether something that happens every `interval` seconds is due this tick.
+    fn is_due(&self, interval: f32) -> bool {
+        let ticks = (interval * TICKS_PER_SECOND as f32) as u64;
+        ticks > 0 && self.tick.is_multiple_of(ticks)
+    }
+
+    // Serves an extra ball from the middle towards one of the sides at random,
+    // if there is room for it.
+    pub fn spawn_ball(&mut self) {
+        if self.balls.len() >= self.rules.multiball.max_balls as usize {
+            return;
+        }
+        let mut direction = Deg(self.rng.gen_range(45.0..135.0));
+        if self.rng.gen() {
+            direction = -direction;
+        }
+        self.balls.push(Ball::serve(direction));
+    }
+
+    // Picks a power-up at random, by weight, and puts it somewhere in the middle
+    // of the court.
+    fn spawn_pickup(&mut self) {
+        let rules = &self.rules.power_ups;
+        let total_weight: u32 = rules.power_ups.iter().map(|power_up| power_up.weight).sum();
+        if self.pickups.len() >= rules.max_pickups as usize || total_weight == 0 {
+            return;
+        }
+        let mut choice = self.rng.gen_range(0..total_weight);
+        let power_up = rules
+            .power_ups
+            .iter()
+            .position(|power_up| {
+                if choice < power_up.weight {
+                    return true;
+                }
+                choice -= power_up.weight;
+                false
+            })
+            .unwrap_or(0);
+        let extent = self.rules.level.extent();
+        let position = Vector2::new(
+            self.rng.gen_range(-0.5..0.5) * extent.x,
+            self.rng.gen_range(-0.6..0.6) * extent.y,
+        );
+        self.pickups.push(Pickup { position, power_up });
+    }
+
+    fn collect(&mut self, power_up: usize, team: usize) {
+        let power_up = &self.rules.power_ups.power_ups[power_up];
+        log::info!("team {} collected {}", team + 1, power_up.name);
+        let effect = ActiveEffect {
+            effect: power_up.effect,
+            target: power_up.target,
+            collector: team,
+            remaining_ticks: (power_up.duration * TICKS_PER_SECOND as f32) as u64,
+        };
+        match effect.effect {
+            Effect::ExtraBalls(count) => {
+                for _ in 0..count {
+                    self.spawn_ball();
+                }
+            }
+            _ => self.effects.push(effect),
+        }
+    }
+
+    // Counts down running power-ups and sizes the paddles for the ones left.
+    // Stacked size power-ups never make a paddle longer than its side.
+    fn update_effects(&mut self) {
+        self.effects.retain_mut(|effect| {
+            effect.remaining_ticks = effect.remaining_ticks.saturating_sub(1);
+            effect.remaining_ticks > 0
+        });
+        let extent = self.rules.level.extent();
+        for paddle in &mut self.paddles {
+            paddle.scale = 1.0;
+            for effect in &self.effects {
+                if let Effect::PaddleSize(scale) = effect.effect {
+                    if effect.applies_to(paddle.team) {
+                        paddle.scale *= scale;
+                    }
+                }
+            }
+            let max_scale = paddle.side.along(extent) / PADDLE_HALF_HEIGHT;
+            paddle.scale = paddle.scale.min(max_scale);
+        }
+    }
+
+    // Power-ups that act on balls only act on the ones last hit by the teams they
+    // apply to. A ball nobody has hit yet only gets the ones for everyone.
+    fn ball_effects<'a>(&'a self, ball: &'a Ball) -> impl Iterator<Item = Effect> + 'a {
+        self.effects
+            .iter()
+            .filter(move |effect| match ball.last_hit {
+                Some(team) => effect.applies_to(team),
+                None => effect.target == Target::Everyone,
+            })
+            .map(|effect| effect.effect)
+    }
+
+    pub fn is_visible(&self, ball: &Ball) -> bool {
+        !self
+            .ball_effects(ball)
+            .any(|effect| effect == Effect::InvisibleBall)
+    }
+
+    pub fn is_shielded(&self, team: usize) -> bool {
+        self.effects
+            .iter()
+            .any(|effect| effect.effect == Effect::Shield && effect.applies_to(team))
+    }
+
+    // Returns the team the ball got past, if it went in. Power-ups the ball
+    // passes through after a hit are added to `collected` with the team that hit
+    // it.
+    fn move_ball(&mut self, ball: &mut Ball, collected: &mut Vec<(usize, usize)>) -> Option<usize> {
+        let mut speed = ball.speed;
+        let effects: Vec<Effect> = self.ball_effects(ball).collect();
+        for effect in effects {
+            match effect {
+                Effect::BallSpeed(scale) => speed *= scale,
+                Effect::Curve(degrees_per_second) => {
+                    ball.direction.0 += degrees_per_second * TICK_SECONDS
+                }
+                _ => {}
+            }
+        }
+        ball.position += ball.heading() * 0.01 * speed;
+        self.stats.max_ball_speed = self.stats.max_ball_speed.max(speed);
+        if let Some(team) = ball.last_hit {
+            self.stats.possession_seconds[team] += TICK_SECONDS;
+        }
+
+        let extent = self.rules.level.extent();
+        for side in Side::ALL {
+            let distance = cgmath::InnerSpace::dot(ball.position, side.normal());
+            if distance <= side.depth(extent) - BALL_HALF_SIZE {
+                continue;
+            }
+            match self.defender(side) {
+                Some(team) if !self.is_shielded(team) => return Some(team),
+                _ => {
+                    ball.bounce_away_from(side, &mut self.rng);
+                }
+            }
+        }
+
+        let ball_left = ball.position.x - BALL_HALF_SIZE;
+        let ball_right = ball.position.x + BALL_HALF_SIZE;
+        let ball_top = ball.position.y + BALL_HALF_SIZE;
+        let ball_bottom = ball.position.y - BALL_HALF_SIZE;
+
+        let max_speed = self.rules.ball.max_speed;
+        for (player, paddle) in self.paddles.iter().enumerate() {
+            if self.is_eliminated(paddle.team) {
+                continue;
+            }
+            let half_size = paddle.half_size();
+            if ball_left < paddle.position.x + half_size.x
+                && ball_right > paddle.position.x - half_size.x
+                && ball_bottom < paddle.position.y + half_size.y
+                && ball_top > paddle.position.y - half_size.y
+                && ball.bounce_away_from(paddle.side, &mut self.rng)
+            {
+                ball.last_hit = Some(paddle.team);
+                ball.hits += 1;
+                let stats = &mut self.stats;
+                stats.longest_rally = stats.longest_rally.max(ball.hits);
+                stats.hits[player] += 1;
+                stats.fastest_returns[player] = stats.fastest_returns[player].max(ball.speed);
+                if self.rules.court == Court::Survival {
+                    self.points += 1;
+                }
+                if let BallSpeedGrowth::PerHit(increment) = self.rules.ball.growth {
+                    ball.speed_up(increment, max_speed);
+                }
+            }
+        }
+
+        self.collide_with_obstacles(ball);
+        self.collide_with_bricks(ball);
+
+        if let Some(team) = ball.last_hit {
+            self.pickups.retain(|pickup| {
+                let offset = pickup.position - ball.position;
+                let reach = PICKUP_HALF_SIZE + BALL_HALF_SIZE;
+                if offset.x.abs() < reach && offset.y.abs() < reach {
+                    collected.push((pickup.power_up, team));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        if let BallSpeedGrowth::Ramp(per_second) = self.rules.ball.growth {
+            ball.speed_up(per_second * TICK_SECONDS, max_speed);
+        }
+        None
+    }
+
+    pub fn obstacle_position(&self, obstacle: usize) -> Vector2<f32> {
+        self.rules.level.obstacles[obstacle].position_at(self.tick as f32 * TICK_SECONDS)
+    }
+
+    // A ball overlapping an obstacle is pushed out through the nearest face and
+    // bounces off it, or goes through a portal it wasn't already in.
+    fn collide_with_obstacles(&mut self, ball: &mut Ball) {
+        let mut in_portal = None;
+        let max_speed = self.rules.ball.max_speed;
+        for (index, obstacle) in self.rules.level.obstacles.iter().enumerate() {
+            let position = self.obstacle_position(index);
+            let (normal, depth) = match ball.overlap(position, obstacle.half_size.into()) {
+                Some(overlap) => overlap,
+                None => continue,
+            };
+            match obstacle.kind {
+                ObstacleKind::Block => {
+                    ball.position += normal * depth;
+                    ball.reflect(normal);
+                }
+                ObstacleKind::Bumper(boost) => {
+                    ball.position += normal * depth;
+                    if ball.reflect(normal) {
+                        ball.speed_up(boost, max_speed);
+                    }
+                }
+                ObstacleKind::Portal(exit) => {
+                    if ball.in_portal == Some(index) {
+                        in_portal = Some(index);
+                    } else if in_portal.is_none() {
+                        ball.position = self.obstacle_position(exit);
+                        in_portal = Some(exit);
+                    }
+                }
+            }
+        }
+        ball.in_portal = in_portal;
+    }
+
+    // A ball bounces off one brick at a time, which takes a hit and breaks once it
+    // has none left. Every hit scores.
+    fn collide_with_bricks(&mut self, ball: &mut Ball) {
+        let hit = self.bricks.iter().position(|brick| {
+            match ball.overlap(brick.position, brick.half_size) {
+                Some((normal, _)) => ball.reflect(normal),
+                None => false,
+            }
+        });
+        if let Some(index) = hit {
+            self.points += BRICK_POINTS;
+            let brick = &mut self.bricks[index];
+            brick.hits_left -= 1;
+            if brick.hits_left == 0 {
+                self.bricks.remove(index);
+            }
+        }
+    }
+
+    pub fn ai_input(&self, player: usize, difficulty: Difficulty) -> PaddleInput {
+        let paddle = self.paddles[player];
+        let normal = paddle.side.normal();
+        // The computer goes after whichever approaching ball is nearest its goal.
+        // An easy opponent only reacts once the ball has crossed into its half.
+        let ball = self
+            .balls
+            .iter()
+            .filter(|ball| cgmath::InnerSpace::dot(ball.heading(), normal) > 0.0)
+            .map(|ball| (ball, cgmath::InnerSpace::dot(ball.position, normal)))
+            .filter(|&(_, distance)| difficulty != Difficulty::Easy || distance > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        let target = match ball {
+            Some((ball, _)) => paddle.side.along(ball.position),
+            None => 0.0,
+        };
+
+        let difference = target - paddle.side.along(paddle.position);
+        PaddleInput::from_buttons(
+            difference > difficulty.dead_zone(),
+            difference < -difficulty.dead_zone(),
+        )
+    }
+
+    // The input speeds the paddle up or slows it down towards the input's share of
+    // the maximum speed. A paddle that runs into the end of its side stops dead.
+    fn move_paddle(&mut self, player: usize, input: PaddleInput) {
+        let rules = self.rules.paddle;
+        let paddle = &mut self.paddles[player];
+        let target_velocity = input.speed() * rules.max_speed;
+        let speeding_up = target_velocity.abs() > paddle.velocity.abs()
+            && (paddle.velocity == 0.0 || target_velocity.signum() == paddle.velocity.signum());
+        let rate = if speeding_up {
+            rules.acceleration
+        } else {
+            rules.deceleration
+        };
+        let max_change = rate * TICK_SECONDS;
+        paddle.velocity += (target_velocity - paddle.velocity).clamp(-max_change, max_change);
+
+        let axis = paddle.side.axis();
+        paddle.position += axis * paddle.velocity * TICK_SECONDS;
+        // Paddles stop where their ends touch the edges of the court.
+        let limit = (paddle.side.along(self.rules.level.extent()) - paddle.half_length()).max(0.0);
+        let position = paddle.side.along(paddle.position);
+        if position.abs() >= limit {
+            let clamped = position.clamp(-limit, limit);
+            paddle.position += axis * (clamped - position);
+            paddle.velocity = 0.0;
+        }
+    }
+
+    // Moves a paddle towards a point as fast as it can while still being able to
+    // brake in time, and slowing down on the last tick so it doesn't overshoot.
+    // Only the part of the point along the paddle's side matters.
+    pub fn input_towards(&self, player: usize, target: Vector2<f32>) -> PaddleInput {
+        let rules = self.rules.paddle;
+        let paddle = self.paddles[player];
+        let difference = paddle.side.along(target) - paddle.side.along(paddle.position);
+        let braking_speed = (2.0 * rules.deceleration * difference.abs()).sqrt();
+        let speed = braking_speed.min(difference.abs() / TICK_SECONDS);
+        PaddleInput::from_axis(speed.copysign(difference) / rules.max_speed)
+    }
+
+    // With points the first to the winning score wins, unless goals on the same
+    // tick leave teams tied at the top; then play goes on until one is ahead.
+    // With lives the last team left wins.
+    fn winner(&self) -> Option<usize> {
+        let mut leaders = if self.rules.court.uses_lives() {
+            let remaining: Vec<usize> = (0..self.score.len())
+                .filter(|&team| !self.is_eliminated(team))
+                .collect();
+            remaining.into_iter()
+        } else {
+            let best = *self.score.iter().max()?;
+            if best < self.rules.win_score {
+                return None;
+            }
+            let leaders: Vec<usize> = (0..self.score.len())
+                .filter(|&team| self.score[team] == best)
+                .collect();
+            leaders.into_iter()
+        };
+        match (leaders.next(), leaders.next()) {
+            (Some(winner), None) => Some(winner),
+            _ => None,
+        }
+    }
+
+    // Knocking out every team that is left on the same tick would leave nobody to
+    // win, so those teams stay in with their last life instead. A single player
+    // is simply out.
+    fn score_goals(&mut self, conceding_teams: &[usize]) {
+        let previous_score = self.score.clone();
+        for &conceding_team in conceding_teams {
+            if self.rules.court.uses_lives() {
+                self.score[conceding_team] = self.score[conceding_team].saturating_sub(1);
+            } else {
+                for (team, score) in self.score.iter_mut().enumerate() {
+                    if team != conceding_team {
+                        *score += 1;
+                    }
+                }
+                if self.rules.court == Court::TimeAttack && conceding_team != 0 {
+                    self.points += 1;
+                }
+            }
+        }
+
+        if !self.rules.court.uses_lives() {
+            return;
+        }
+        if self.score.len() > 1 && (0..self.score.len()).all(|team| self.is_eliminated(team)) {
+            for (score, &previous_score) in self.score.iter_mut().zip(&previous_score) {
+                if previous_score > 0 {
+                    *score = 1;
+                }
+            }
+        }
+        for (team, &previous_score) in previous_score.iter().enumerate() {
+            if previous_score > 0 && self.is_eliminated(team) {
+                log::info!("team {} is out", team + 1);
+            }
+        }
+    }
+
+    fn reset_objects(&mut self, serve_direction: Deg<f32>) {
+        self.balls = vec![Ball::serve(serve_direction)];
+        let extent = self.rules.level.extent();
+        for paddle in &mut self.paddles {
+            *paddle = Paddle::new(paddle.team, paddle.side, paddle.distance, extent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ai_frame(simulation: &Simulation) -> InputFrame {
+        let mut frame = InputFrame::default();
+        for player in 0..simulation.paddles.len() {
+            frame.paddles[player] = simulation.ai_input(player, Difficulty::Normal);
+        }
+        frame
+    }
+
+    // Everything that moves or scores, to compare runs by.
+    fn state(simulation: &Simulation) -> String {
+        format!(
+            "{} {:?} {:?} {:?} {:?} {}",
+            simulation.tick,
+            simulation.score,
+            simulation.paddles,
+            simulation.balls,
+            simulation.pickups.len(),
+            simulation.points
+        )
+    }
+
+    fn run(seed: u64, rules: &MatchRules, ticks: u64) -> Vec<String> {
+        let mut simulation = Simulation::new(seed, rules.clone());
+        let mut states = Vec::new();
+        while simulation.tick < ticks {
+            let result = simulation.step(ai_frame(&simulation));
+            states.push(state(&simulation));
+            if result.is_some() {
+                break;
+            }
+        }
+        states
+    }
+
+    #[test]
+    fn same_seed_and_input_give_the_same_match() {
+        let mut rules = MatchRules {
+            court: Court::Doubles,
+            ..MatchRules::default()
+        };
+        rules.multiball.spawn_interval = 3.0;
+        rules.power_ups.spawn_interval = 2.0;
+        let first = run(5, &rules, 3000);
+        assert_eq!(first, run(5, &rules, 3000));
+        assert_ne!(first, run(6, &rules, 3000));
+    }
+
+    #[test]
+    fn versus_ends_when_a_team_reaches_the_win_score() {
+        let rules = MatchRules {
+            win_score: 2,
+            ..MatchRules::default()
+        };
+        let mut simulation = Simulation::new(3, rules);
+        // The first paddle stands still, so the computer can't keep the rally going.
+        let result = loop {
+            let mut frame = InputFrame::default();
+            frame.paddles[1] = simulation.ai_input(1, Difficulty::Hard);
+            if let Some(result) = simulation.step(frame) {
+                break result;
+            }
+            assert!(simulation.tick < 1_000_000, "match did not end");
+        };
+        assert_eq!(result.winner, Some(1));
+        assert_eq!(result.score[1], 2);
+        assert_eq!(result.score, simulation.score);
+    }
+
+    #[test]
+    fn rules_outside_the_command_line_limits_are_invalid() {
+        assert!(MatchRules::default().validate().is_ok());
+        for rules in [
+            MatchRules {
+                win_score: 0,
+                ..MatchRules::default()
+            },
+            MatchRules {
+                lives: 0,
+                ..MatchRules::default()
+            },
+            MatchRules {
+                time_limit: 0.0,
+                ..MatchRules::default()
+            },
+        ] {
+            assert!(rules.validate().is_err());
+        }
+    }
+}
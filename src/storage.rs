@@ -0,0 +1,121 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Game data that isn't settings, like high scores, is kept as JSON files in the
+// platform data directory, or under a `pong-wgpu-` key in local storage in the
+// browser. Nothing saved yet reads as `None`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, String> {
+    let path = match data_path(name) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|error| format!("{}: {}", path.display(), error)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(format!("{}: {}", path.display(), error)),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, String> {
+    let storage = crate::settings::local_storage();
+    match storage.and_then(|storage| storage.get_item(&storage_key(name)).ok().flatten()) {
+        Some(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|error| error.to_string()),
+        None => Ok(None),
+    }
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(value).map_err(|error| error.to_string())?;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = data_path(name) {
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, contents))
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Some(storage) = crate::settings::local_storage() {
+        storage
+            .set_item(&storage_key(name), &contents)
+            .map_err(|_| "could not write to local storage".to_string())?;
+    }
+
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = data_path(name) {
+        match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("{}: {}", path.display(), error));
+            }
+            _ => {}
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Some(storage) = crate::settings::local_storage() {
+        storage
+            .remove_item(&storage_key(name))
+            .map_err(|_| "could not write to local storage".to_string())?;
+    }
+
+    Ok(())
+}
+
+// Adds a line of JSON to a log that is never read back whole, like the match
+// history, so saving doesn't get slower as it grows and a crash can at worst
+// cut off the last line.
+pub fn append<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let line = serde_json::to_string(value).map_err(|error| error.to_string())? + "\n";
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(directory) = data_directory() {
+        use std::io::Write;
+        let path = directory.join(format!("{}.jsonl", name));
+        std::fs::create_dir_all(&directory)
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+            })
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Some(storage) = crate::settings::local_storage() {
+        let key = storage_key(name);
+        let contents = storage.get_item(&key).ok().flatten().unwrap_or_default() + &line;
+        storage
+            .set_item(&key, &contents)
+            .map_err(|_| "could not write to local storage".to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn data_path(name: &str) -> Option<std::path::PathBuf> {
+    data_directory().map(|directory| directory.join(format!("{}.json", name)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn data_directory() -> Option<std::path::PathBuf> {
+    dirs::data_dir().map(|directory| directory.join("pong-wgpu"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage_key(name: &str) -> String {
+    format!("pong-wgpu-{}", name.replace('_', "-"))
+}
@@ -0,0 +1,483 @@
+use crate::renderer::Viewport;
+use crate::settings::Theme;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// One empty column between characters.
+const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+// Atlas cells keep a transparent border around each glyph so neighbours never
+// bleed into each other when scaled.
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 2;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+const ATLAS_COLUMNS: u32 = 16;
+const PANEL_PADDING: f32 = 16.0;
+
+// Each row is five pixels wide, with the leftmost pixel in the highest bit.
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; GLYPH_HEIGHT as usize])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('\'', [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('<', [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010]),
+    ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+];
+
+// The font only has capitals; anything else it doesn't know is drawn as '?'.
+fn glyph_index(character: char) -> usize {
+    let character = character.to_ascii_uppercase();
+    let find = |character| GLYPHS.iter().position(|(glyph, _)| *glyph == character);
+    find(character).or_else(|| find('?')).unwrap_or(0)
+}
+
+// The cell after the last glyph is filled in completely, for drawing panels.
+fn solid_index() -> usize {
+    GLYPHS.len()
+}
+
+fn atlas_size() -> (u32, u32) {
+    let cells = GLYPHS.len() as u32 + 1;
+    let rows = cells.div_ceil(ATLAS_COLUMNS);
+    (ATLAS_COLUMNS * CELL_WIDTH, rows * CELL_HEIGHT)
+}
+
+// White pixels with the glyph shapes in the alpha channel, tinted when drawn.
+fn atlas_pixels() -> Vec<u8> {
+    let (width, height) = atlas_size();
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    let mut set_pixel = |index: usize, x: u32, y: u32| {
+        let (left, top) = cell_origin(index);
+        let offset = (((top + 1 + y) * width + left + 1 + x) * 4) as usize;
+        pixels[offset..offset + 4].copy_from_slice(&[255; 4]);
+    };
+    for (index, (_, rows)) in GLYPHS.iter().enumerate() {
+        for (y, bits) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    set_pixel(index, x, y as u32);
+                }
+            }
+        }
+    }
+    for y in 0..GLYPH_HEIGHT {
+        for x in 0..GLYPH_WIDTH {
+            set_pixel(solid_index(), x, y);
+        }
+    }
+    pixels
+}
+
+fn cell_origin(index: usize) -> (u32, u32) {
+    let index = index as u32;
+    (
+        index % ATLAS_COLUMNS * CELL_WIDTH,
+        index / ATLAS_COLUMNS * CELL_HEIGHT,
+    )
+}
+
+// The texture coordinates of a glyph, without the cell border.
+fn glyph_uv(index: usize) -> [[f32; 2]; 2] {
+    let (width, height) = atlas_size();
+    let (left, top) = cell_origin(index);
+    [
+        [
+            (left + 1) as f32 / width as f32,
+            (top + 1) as f32 / height as f32,
+        ],
+        [
+            (left + 1 + GLYPH_WIDTH) as f32 / width as f32,
+            (top + 1 + GLYPH_HEIGHT) as f32 / height as f32,
+        ],
+    ]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+// A line of text positioned in logical pixels. `x` is the left edge, centre or
+// right edge depending on the alignment, and `y` is the vertical centre. The
+// scale is the size of one font pixel, and text without a colour uses the theme.
+#[derive(Clone, Debug)]
+pub struct Text {
+    pub string: String,
+    pub x: f32,
+    pub y: f32,
+    pub alignment: Alignment,
+    pub scale: f32,
+    pub color: Option<[f32; 3]>,
+}
+
+impl Text {
+    pub fn new(string: impl Into<String>, x: f32, y: f32) -> Self {
+        Text {
+            string: string.into(),
+            x,
+            y,
+            alignment: Alignment::Center,
+            scale: 3.0,
+            color: None,
+        }
+    }
+
+    pub fn aligned(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn scaled(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn colored(mut self, color: [f32; 3]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    // Left, top, right and bottom edges in logical pixels. The spacing after the
+    // last character isn't counted.
+    pub fn bounds(&self) -> [f32; 4] {
+        let characters = self.string.chars().count() as u32;
+        let width = (characters * GLYPH_ADVANCE).saturating_sub(GLYPH_ADVANCE - GLYPH_WIDTH);
+        let width = width as f32 * self.scale;
+        let height = GLYPH_HEIGHT as f32 * self.scale;
+        let left = match self.alignment {
+            Alignment::Left => self.x,
+            Alignment::Center => self.x - width / 2.0,
+            Alignment::Right => self.x - width,
+        };
+        let top = self.y - height / 2.0;
+        [left, top, left + width, top + height]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+// Draws text as one textured quad per character, sampled from a glyph atlas made
+// from the font above. Plain RGBA textures and nearest filtering keep it working
+// on WebGL.
+pub struct TextRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let (width, height) = atlas_size();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let atlas = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Glyph Atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &atlas,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &atlas_pixels(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(width * 4),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+        let atlas_view = atlas.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Atlas Sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Text Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Text Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Text Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("text.wgsl").into()),
+            flags: wgpu::ShaderFlags::all(),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<TextVertex>() as u64,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x2,
+                            offset: 0,
+                            shader_location: 0,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x2,
+                            offset: std::mem::size_of::<[f32; 2]>() as u64,
+                            shader_location: 1,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x4,
+                            offset: std::mem::size_of::<[[f32; 2]; 2]>() as u64,
+                            shader_location: 2,
+                        },
+                    ],
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        let (vertex_buffer, index_buffer) = TextRenderer::buffers(device, &[], &[]);
+        TextRenderer {
+            render_pipeline,
+            bind_group,
+            vertex_buffer,
+            index_buffer,
+            index_count: 0,
+        }
+    }
+
+    fn buffers(
+        device: &wgpu::Device,
+        vertices: &[TextVertex],
+        indices: &[u16],
+    ) -> (wgpu::Buffer, wgpu::Buffer) {
+        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Text Vertex Buffer"),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            },
+        );
+        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Text Index Buffer"),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsage::INDEX,
+            },
+        );
+        (vertex_buffer, index_buffer)
+    }
+
+    // HUD text is drawn straight over the court. Menu text gets a panel in the
+    // background colour behind it so it stays readable.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        viewport: Viewport,
+        theme: &Theme,
+        hud: &[Text],
+        menu: &[Text],
+    ) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut quad = |[left, top, right, bottom]: [f32; 4], index: usize, color: [f32; 3]| {
+            let [[u0, v0], [u1, v1]] = glyph_uv(index);
+            let color = [color[0], color[1], color[2], 1.0];
+            let first_vertex = vertices.len() as u16;
+            for (x, y, u, v) in [
+                (left, top, u0, v0),
+                (left, bottom, u0, v1),
+                (right, top, u1, v0),
+                (right, bottom, u1, v1),
+            ] {
+                vertices.push(TextVertex {
+                    position: viewport.point_to_clip(x, y),
+                    uv: [u, v],
+                    color,
+                });
+            }
+            indices.extend([0, 1, 2, 2, 1, 3].iter().map(|i| first_vertex + i));
+        };
+
+        let menu_bounds = menu.iter().map(Text::bounds).reduce(|a, b| {
+            [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]
+        });
+        if let Some([left, top, right, bottom]) = menu_bounds {
+            let panel = [
+                left - PANEL_PADDING,
+                top - PANEL_PADDING,
+                right + PANEL_PADDING,
+                bottom + PANEL_PADDING,
+            ];
+            quad(panel, solid_index(), theme.background);
+        }
+
+        for text in hud.iter().chain(menu) {
+            let [left, top, _, bottom] = text.bounds();
+            let color = text.color.unwrap_or(theme.foreground);
+            for (i, character) in text.string.chars().enumerate() {
+                let glyph_left = left + (i as u32 * GLYPH_ADVANCE) as f32 * text.scale;
+                let glyph_right = glyph_left + GLYPH_WIDTH as f32 * text.scale;
+                quad(
+                    [glyph_left, top, glyph_right, bottom],
+                    glyph_index(character),
+                    color,
+                );
+            }
+        }
+
+        // The index buffer is 16 bit, which is plenty for a screen of text.
+        if vertices.len() > u16::MAX as usize {
+            log::warn!("too much text to draw, skipping it");
+            vertices.clear();
+            indices.clear();
+        }
+        let (vertex_buffer, index_buffer) = TextRenderer::buffers(device, &vertices, &indices);
+        self.vertex_buffer = vertex_buffer;
+        self.index_buffer = index_buffer;
+        self.index_count = indices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.index_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
@@ -0,0 +1,30 @@
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct FragmentInput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var glyph_atlas: texture_2d<f32>;
+[[group(0), binding(1)]]
+var glyph_sampler: sampler;
+
+[[stage(vertex)]]
+fn main(in: VertexInput) -> FragmentInput {
+    var fragment_input: FragmentInput;
+    fragment_input.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    fragment_input.uv = in.uv;
+    fragment_input.color = in.color;
+    return fragment_input;
+};
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    return in.color * textureSample(glyph_atlas, glyph_sampler, in.uv);
+};
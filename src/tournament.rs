@@ -0,0 +1,344 @@
+use crate::storage;
+use serde::{Deserialize, Serialize};
+
+const STORAGE_NAME: &str = "tournament";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    SingleElimination,
+    DoubleElimination,
+    RoundRobin,
+}
+
+// Players are indices into the tournament's list of names. The score is each
+// side's goals, or lives left on courts with lives.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub round: u32,
+    pub players: [usize; 2],
+    pub result: Option<FixtureResult>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureResult {
+    pub winner: usize,
+    pub score: [u32; 2],
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Standing {
+    pub player: usize,
+    pub played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub points_for: u32,
+    pub points_against: u32,
+}
+
+// Elimination brackets are drawn a round at a time from the players still in,
+// in the order they were entered, with the odd one out getting a bye. In double
+// elimination players are out after their second loss; unbeaten players only
+// meet each other and so do players with a loss, until the last two meet in the
+// final. A round robin is drawn up front so everyone plays everyone once.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tournament {
+    pub format: Format,
+    pub players: Vec<String>,
+    pub fixtures: Vec<Fixture>,
+}
+
+impl Tournament {
+    pub fn new(format: Format, players: Vec<String>) -> Self {
+        let mut tournament = Tournament {
+            format,
+            players,
+            fixtures: Vec::new(),
+        };
+        match format {
+            Format::RoundRobin => tournament.draw_round_robin(),
+            _ => tournament.draw_next_round(),
+        }
+        tournament
+    }
+
+    pub fn load() -> Result<Option<Self>, String> {
+        storage::load(STORAGE_NAME)
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(STORAGE_NAME, self) {
+            log::error!("could not save tournament: {}", error);
+        }
+    }
+
+    // A finished tournament is forgotten, so there is nothing left to resume.
+    pub fn remove() {
+        if let Err(error) = storage::remove(STORAGE_NAME) {
+            log::error!("could not remove finished tournament: {}", error);
+        }
+    }
+
+    // The circle method: the first player stays put while the rest rotate, and
+    // a missing player in an odd field means a bye.
+    fn draw_round_robin(&mut self) {
+        let mut seats: Vec<Option<usize>> = (0..self.players.len()).map(Some).collect();
+        if seats.len() % 2 == 1 {
+            seats.push(None);
+        }
+        for round in 0..seats.len().saturating_sub(1) {
+            for i in 0..seats.len() / 2 {
+                if let (Some(a), Some(b)) = (seats[i], seats[seats.len() - 1 - i]) {
+                    self.fixtures.push(Fixture {
+                        round: round as u32,
+                        players: [a, b],
+                        result: None,
+                    });
+                }
+            }
+            seats[1..].rotate_right(1);
+        }
+    }
+
+    fn draw_next_round(&mut self) {
+        let round = self.fixtures.last().map_or(0, |fixture| fixture.round + 1);
+        let groups = match self.format {
+            Format::SingleElimination => vec![self.remaining(0)],
+            Format::DoubleElimination => {
+                let unbeaten = self.remaining(0);
+                let one_loss: Vec<usize> = self
+                    .remaining(1)
+                    .into_iter()
+                    .filter(|player| !unbeaten.contains(player))
+                    .collect();
+                if unbeaten.len() + one_loss.len() == 2 {
+                    vec![unbeaten.into_iter().chain(one_loss).collect()]
+                } else {
+                    vec![unbeaten, one_loss]
+                }
+            }
+            Format::RoundRobin => return,
+        };
+        for group in groups {
+            for pair in group.chunks_exact(2) {
+                self.fixtures.push(Fixture {
+                    round,
+                    players: [pair[0], pair[1]],
+                    result: None,
+                });
+            }
+        }
+    }
+
+    // Players with at most this many losses.
+    fn remaining(&self, max_losses: u32) -> Vec<usize> {
+        let standings = self.standings_by_player();
+        (0..self.players.len())
+            .filter(|&player| standings[player].losses <= max_losses)
+            .collect()
+    }
+
+    pub fn next_fixture(&self) -> Option<usize> {
+        self.fixtures
+            .iter()
+            .position(|fixture| fixture.result.is_none())
+    }
+
+    // Elimination brackets draw the next round once every match in this one has
+    // been played.
+    pub fn record(&mut self, fixture: usize, winner: usize, score: [u32; 2]) {
+        self.fixtures[fixture].result = Some(FixtureResult { winner, score });
+        if self.next_fixture().is_none() && self.champion().is_none() {
+            self.draw_next_round();
+        }
+    }
+
+    pub fn champion(&self) -> Option<usize> {
+        if self.next_fixture().is_some() {
+            return None;
+        }
+        match self.format {
+            Format::SingleElimination => only(self.remaining(0)),
+            Format::DoubleElimination => only(self.remaining(1)),
+            Format::RoundRobin => self.standings().first().map(|standing| standing.player),
+        }
+    }
+
+    fn standings_by_player(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = (0..self.players.len())
+            .map(|player| Standing {
+                player,
+                ..Standing::default()
+            })
+            .collect();
+        for fixture in &self.fixtures {
+            let result = match &fixture.result {
+                Some(result) => result,
+                None => continue,
+            };
+            for (slot, &player) in fixture.players.iter().enumerate() {
+                let standing = &mut standings[player];
+                standing.played += 1;
+                if player == result.winner {
+                    standing.wins += 1;
+                } else {
+                    standing.losses += 1;
+                }
+                standing.points_for += result.score[slot];
+                standing.points_against += result.score[1 - slot];
+            }
+        }
+        standings
+    }
+
+    // Most wins first, then fewest losses, then the best points difference.
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings = self.standings_by_player();
+        standings.sort_by_key(|standing| {
+            (
+                std::cmp::Reverse(standing.wins),
+                standing.losses,
+                std::cmp::Reverse(standing.points_for as i64 - standing.points_against as i64),
+            )
+        });
+        standings
+    }
+}
+
+fn only(players: Vec<usize>) -> Option<usize> {
+    match players[..] {
+        [player] => Some(player),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tournament(format: Format, players: usize) -> Tournament {
+        Tournament::new(
+            format,
+            (0..players).map(|player| player.to_string()).collect(),
+        )
+    }
+
+    // Plays fixtures as they come up, the lower numbered player always winning,
+    // until there is a champion.
+    fn play_out(tournament: &mut Tournament) -> usize {
+        let mut matches = 0;
+        while let Some(fixture) = tournament.next_fixture() {
+            let [a, b] = tournament.fixtures[fixture].players;
+            let score = if a < b { [3, 1] } else { [1, 3] };
+            tournament.record(fixture, a.min(b), score);
+            matches += 1;
+            assert!(matches < 100, "tournament did not finish");
+        }
+        tournament
+            .champion()
+            .expect("finished tournaments have a champion")
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once_a_round_at_most() {
+        for players in [2, 4, 5, 7] {
+            let tournament = tournament(Format::RoundRobin, players);
+            assert_eq!(tournament.fixtures.len(), players * (players - 1) / 2);
+            let mut pairs: Vec<[usize; 2]> = tournament
+                .fixtures
+                .iter()
+                .map(|fixture| {
+                    let [a, b] = fixture.players;
+                    [a.min(b), a.max(b)]
+                })
+                .collect();
+            pairs.sort_unstable();
+            pairs.dedup();
+            assert_eq!(pairs.len(), tournament.fixtures.len());
+
+            let rounds = tournament.fixtures.last().unwrap().round + 1;
+            assert_eq!(rounds as usize, players + players % 2 - 1);
+            for round in 0..rounds {
+                let mut seen: Vec<usize> = tournament
+                    .fixtures
+                    .iter()
+                    .filter(|fixture| fixture.round == round)
+                    .flat_map(|fixture| fixture.players)
+                    .collect();
+                let count = seen.len();
+                seen.sort_unstable();
+                seen.dedup();
+                assert_eq!(seen.len(), count);
+                // An odd field leaves one player out each round.
+                assert_eq!(count, players - players % 2);
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_champion_has_the_most_wins() {
+        let mut tournament = tournament(Format::RoundRobin, 5);
+        assert_eq!(play_out(&mut tournament), 0);
+        let standings = tournament.standings();
+        assert_eq!(standings[0].wins, 4);
+        assert_eq!(standings[4].losses, 4);
+    }
+
+    #[test]
+    fn single_elimination_gives_the_odd_player_a_bye() {
+        let mut tournament = tournament(Format::SingleElimination, 5);
+        let first_round: Vec<[usize; 2]> = tournament
+            .fixtures
+            .iter()
+            .map(|fixture| fixture.players)
+            .collect();
+        assert_eq!(first_round, vec![[0, 1], [2, 3]]);
+        assert_eq!(play_out(&mut tournament), 0);
+        // 5 players need 4 matches to knock out all but one.
+        assert_eq!(tournament.fixtures.len(), 4);
+        assert!(tournament
+            .fixtures
+            .iter()
+            .any(|fixture| fixture.players.contains(&4)));
+    }
+
+    #[test]
+    fn double_elimination_knocks_players_out_on_their_second_loss() {
+        let mut tournament = tournament(Format::DoubleElimination, 4);
+        assert_eq!(play_out(&mut tournament), 0);
+        let standings = tournament.standings_by_player();
+        assert_eq!(standings[0].losses, 0);
+        for standing in &standings[1..] {
+            assert_eq!(standing.losses, 2);
+        }
+        // Only unbeaten players and only players with a loss meet before the final.
+        let final_round = tournament.fixtures.last().unwrap().round;
+        for fixture in &tournament.fixtures {
+            if fixture.round == final_round {
+                continue;
+            }
+            let losses_before = |player: usize| {
+                tournament
+                    .fixtures
+                    .iter()
+                    .filter(|earlier| earlier.round < fixture.round)
+                    .filter(|earlier| {
+                        earlier.players.contains(&player)
+                            && earlier.result.as_ref().unwrap().winner != player
+                    })
+                    .count()
+            };
+            let [a, b] = fixture.players;
+            assert_eq!(losses_before(a), losses_before(b));
+        }
+    }
+
+    #[test]
+    fn there_is_no_champion_until_the_last_match() {
+        let mut tournament = tournament(Format::SingleElimination, 2);
+        assert_eq!(tournament.champion(), None);
+        tournament.record(0, 1, [2, 5]);
+        assert_eq!(tournament.champion(), Some(1));
+        assert_eq!(tournament.next_fixture(), None);
+    }
+}